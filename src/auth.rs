@@ -0,0 +1,306 @@
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::{
+    self as aw,
+    dev::{forward_ready, AnyBody, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, Method},
+};
+
+use crate::error::SiteError;
+
+/// Credentials which must be supplied via HTTP Basic auth to access admin endpoints.
+#[derive(Clone, Debug)]
+pub struct AdminCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl AdminCredentials {
+    /// Returns true if the given `Authorization` header value contains Basic auth credentials
+    /// matching these ones.
+    fn check_header(&self, auth_header: &str) -> bool {
+        // The scheme name is case-insensitive (RFC 7617)
+        let encoded = match auth_header.trim_start().split_once(' ') {
+            Some((scheme, encoded)) if scheme.eq_ignore_ascii_case("Basic") => encoded.trim(),
+            _ => return false,
+        };
+
+        let decoded = match base64::decode(encoded) {
+            Ok(decoded) => decoded,
+            Err(_) => return false,
+        };
+
+        let (username, password) = match decoded.iter().position(|&b| b == b':') {
+            Some(i) => (&decoded[..i], &decoded[i + 1..]),
+            None => return false,
+        };
+
+        // Evaluate both comparisons so that the time taken does not reveal which one failed
+        let username_ok = constant_time_eq(username, self.username.as_bytes());
+        let password_ok = constant_time_eq(password, self.password.as_bytes());
+
+        username_ok & password_ok
+    }
+}
+
+/// Compares two byte strings in time dependent only on their lengths, not their contents.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+/// Returns true if the request was made by a page on another site. Browsers resend Basic auth
+/// credentials with any request to the site, including form submissions from other sites, so the
+/// credentials alone do not show that the admin meant to make the request.
+///
+/// `Sec-Fetch-Site` is used where the browser sends it, falling back to comparing the `Origin`
+/// header against the requested host. Requests with neither header are not from a browser which
+/// could be tricked into sending them, so they are allowed.
+fn is_cross_site(req: &ServiceRequest) -> bool {
+    if let Some(fetch_site) = req.headers().get("sec-fetch-site") {
+        // "none" means the user made the request themselves, e.g. by typing the URL
+        return !matches!(fetch_site.as_bytes(), b"same-origin" | b"none");
+    }
+
+    if let Some(origin) = req.headers().get(header::ORIGIN) {
+        let origin_host = origin
+            .to_str()
+            .ok()
+            .and_then(|origin| origin.split_once("://"))
+            .map(|(_, host)| host);
+
+        // An origin of "null" has no host, so it is treated as cross-site
+        return origin_host != Some(req.connection_info().host());
+    }
+
+    false
+}
+
+/// Middleware which rejects any request that does not carry valid admin credentials with a
+/// 401 response. If no admin credentials are configured for this instance, every request is
+/// rejected. Requests which could change data are also rejected with a 403 response if they come
+/// from another site, to protect against cross-site request forgery.
+pub struct RequireAdmin {
+    credentials: Rc<Option<AdminCredentials>>,
+}
+
+impl RequireAdmin {
+    pub fn new(credentials: Option<AdminCredentials>) -> Self {
+        Self {
+            credentials: Rc::new(credentials),
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RequireAdmin
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<AnyBody>, Error = aw::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<AnyBody>;
+    type Error = aw::Error;
+    type Transform = RequireAdminMiddleware<S>;
+    type InitError = ();
+    type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ready(Ok(RequireAdminMiddleware {
+            service,
+            credentials: self.credentials.clone(),
+        }))
+    }
+}
+
+pub struct RequireAdminMiddleware<S> {
+    service: S,
+    credentials: Rc<Option<AdminCredentials>>,
+}
+
+impl<S> Service<ServiceRequest> for RequireAdminMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<AnyBody>, Error = aw::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<AnyBody>;
+    type Error = aw::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let safe_method = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+        if !safe_method && is_cross_site(&req) {
+            let err = SiteError::Forbidden
+                .report(format!("cross-site admin request to {}", req.path()));
+            return Box::pin(future::ready(Ok(req.error_response(err))));
+        }
+
+        let auth_header = req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|val| val.to_str().ok());
+
+        let authorized = match (self.credentials.as_ref(), auth_header) {
+            (Some(credentials), Some(auth_header)) => credentials.check_header(auth_header),
+            _ => false,
+        };
+
+        if authorized {
+            Box::pin(self.service.call(req))
+        } else {
+            let err = SiteError::Unauthorized
+                .report(format!("unauthorized admin request to {}", req.path()));
+            // Respond with the error rather than returning it, so that outer middleware still
+            // gets to add its headers to the response
+            Box::pin(future::ready(Ok(req.error_response(err))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+
+    use super::*;
+
+    fn credentials() -> AdminCredentials {
+        AdminCredentials {
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        }
+    }
+
+    fn basic_auth(username: &str, password: &str) -> String {
+        format!("Basic {}", base64::encode(format!("{}:{}", username, password)))
+    }
+
+    async fn post_admin(headers: &[(&str, &str)]) -> StatusCode {
+        let app = test::init_service(App::new()
+            .service(web::scope("/admin")
+                .wrap(RequireAdmin::new(Some(credentials())))
+                .route("/action", web::post().to(HttpResponse::Ok))))
+            .await;
+
+        let mut req = test::TestRequest::post()
+            .uri("/admin/action")
+            .insert_header((header::HOST, "example.org"))
+            .insert_header((header::AUTHORIZATION, basic_auth("admin", "hunter2")));
+
+        for &header in headers {
+            req = req.insert_header(header);
+        }
+
+        test::call_service(&app, req.to_request()).await.status()
+    }
+
+    #[test]
+    fn accepts_correct_credentials() {
+        assert!(credentials().check_header(&basic_auth("admin", "hunter2")));
+    }
+
+    #[test]
+    fn accepts_any_case_scheme() {
+        let encoded = base64::encode("admin:hunter2");
+        assert!(credentials().check_header(&format!("basic {}", encoded)));
+        assert!(credentials().check_header(&format!("BASIC  {}", encoded)));
+    }
+
+    #[test]
+    fn rejects_wrong_credentials() {
+        assert!(!credentials().check_header(&basic_auth("admin", "hunter3")));
+        assert!(!credentials().check_header(&basic_auth("root", "hunter2")));
+        assert!(!credentials().check_header(&basic_auth("admin", "")));
+        assert!(!credentials().check_header(&format!("Bearer {}", base64::encode("admin:hunter2"))));
+        assert!(!credentials().check_header("Basic"));
+        assert!(!credentials().check_header("Basic not-base64!"));
+        assert!(!credentials().check_header(&format!("Basic {}", base64::encode("adminhunter2"))));
+    }
+
+    async fn get_admin(credentials: Option<AdminCredentials>, auth_header: Option<&str>) -> ServiceResponse {
+        let app = test::init_service(App::new()
+            .service(web::scope("/admin")
+                .wrap(RequireAdmin::new(credentials))
+                .route("/page", web::get().to(HttpResponse::Ok))))
+            .await;
+
+        let mut req = test::TestRequest::get().uri("/admin/page");
+        if let Some(auth_header) = auth_header {
+            req = req.insert_header((header::AUTHORIZATION, auth_header));
+        }
+
+        test::call_service(&app, req.to_request()).await
+    }
+
+    #[actix_rt::test]
+    async fn middleware_allows_correct_credentials() {
+        let res = get_admin(Some(credentials()), Some(&basic_auth("admin", "hunter2"))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn middleware_rejects_wrong_credentials() {
+        let res = get_admin(Some(credentials()), Some(&basic_auth("admin", "wrong"))).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[actix_rt::test]
+    async fn middleware_rejects_missing_credentials() {
+        let res = get_admin(Some(credentials()), None).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+
+    #[actix_rt::test]
+    async fn middleware_rejects_everything_without_configured_credentials() {
+        let res = get_admin(None, Some(&basic_auth("admin", "hunter2"))).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn allows_same_origin_requests() {
+        assert_eq!(post_admin(&[]).await, StatusCode::OK);
+        assert_eq!(post_admin(&[("sec-fetch-site", "same-origin")]).await, StatusCode::OK);
+        assert_eq!(post_admin(&[("sec-fetch-site", "none")]).await, StatusCode::OK);
+        assert_eq!(post_admin(&[("origin", "https://example.org")]).await, StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn rejects_cross_site_requests() {
+        assert_eq!(post_admin(&[("sec-fetch-site", "cross-site")]).await, StatusCode::FORBIDDEN);
+        assert_eq!(post_admin(&[("sec-fetch-site", "same-site")]).await, StatusCode::FORBIDDEN);
+        assert_eq!(post_admin(&[("origin", "https://evil.example")]).await, StatusCode::FORBIDDEN);
+        assert_eq!(post_admin(&[("origin", "null")]).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn sec_fetch_site_takes_precedence_over_origin() {
+        let headers = [("sec-fetch-site", "cross-site"), ("origin", "https://example.org")];
+        assert_eq!(post_admin(&headers).await, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn allows_cross_site_get_requests() {
+        let app = test::init_service(App::new()
+            .service(web::scope("/admin")
+                .wrap(RequireAdmin::new(Some(credentials())))
+                .route("/page", web::get().to(HttpResponse::Ok))))
+            .await;
+
+        let req = test::TestRequest::get()
+            .uri("/admin/page")
+            .insert_header((header::AUTHORIZATION, basic_auth("admin", "hunter2")))
+            .insert_header(("sec-fetch-site", "cross-site"))
+            .to_request();
+
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(test)]
+use std::cell::Cell;
+
+use once_cell::sync::OnceCell;
+
+/// The path prefix the archive is served under, such as "/robots", or an empty string if it is
+/// served from the root of the domain. Error pages are rendered without access to the app data,
+/// so this is stored globally rather than in the instance metadata.
+static BASE_PATH: OnceCell<String> = OnceCell::new();
+
+/// Sets the base path to serve the archive under. Leading and trailing slashes are optional, so
+/// "robots", "/robots" and "/robots/" are all equivalent. Has no effect if the base path has
+/// already been set.
+pub(crate) fn init(base_path: &str) {
+    BASE_PATH.set(normalise(base_path)).ok();
+}
+
+fn normalise(base_path: &str) -> String {
+    let base_path = base_path.trim_matches('/');
+
+    if base_path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", base_path)
+    }
+}
+
+/// Returns the base path, which either is empty or starts with a slash and does not end with one.
+pub(crate) fn get() -> &'static str {
+    #[cfg(test)]
+    if let Some(base_path) = tests::OVERRIDE.with(Cell::get) {
+        return base_path;
+    }
+
+    BASE_PATH.get().map(String::as_str).unwrap_or("")
+}
+
+/// Returns a link to the given path on this site, which should start with a slash.
+pub(crate) fn link(path: &str) -> String {
+    let base_path = get();
+
+    let mut buffer = String::with_capacity(base_path.len() + path.len());
+    buffer.push_str(base_path);
+    buffer.push_str(path);
+    buffer
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    thread_local! {
+        /// The base path for tests on this thread. The real base path can only be set once for the
+        /// whole process, so tests use this instead to avoid affecting each other.
+        // A const initialiser needs a newer Rust than the one used for Docker builds
+        #[allow(clippy::missing_const_for_thread_local)]
+        pub(super) static OVERRIDE: Cell<Option<&'static str>> = Cell::new(None);
+    }
+
+    /// Sets the base path for the current thread, as [init] would for the whole process, until
+    /// the returned guard is dropped.
+    pub(crate) fn set_for_thread(base_path: &str) -> BasePathGuard {
+        // Leaked so that it can be returned from `get` like the real base path; tests only set a
+        // handful of short paths
+        let base_path: &'static str = Box::leak(normalise(base_path).into_boxed_str());
+        BasePathGuard(OVERRIDE.with(|current| current.replace(Some(base_path))))
+    }
+
+    pub(crate) struct BasePathGuard(Option<&'static str>);
+
+    impl Drop for BasePathGuard {
+        fn drop(&mut self) {
+            OVERRIDE.with(|current| current.set(self.0));
+        }
+    }
+
+    #[test]
+    fn base_path_is_normalised() {
+        assert_eq!(normalise(""), "");
+        assert_eq!(normalise("/"), "");
+        assert_eq!(normalise("robots"), "/robots");
+        assert_eq!(normalise("/robots/"), "/robots");
+        assert_eq!(normalise("small/robots/"), "/small/robots");
+    }
+
+    #[test]
+    fn links_are_prefixed() {
+        assert_eq!(link("/all"), "/all");
+
+        let _base_path = set_for_thread("/archive/");
+        assert_eq!(get(), "/archive");
+        assert_eq!(link("/all"), "/archive/all");
+    }
+}
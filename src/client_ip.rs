@@ -0,0 +1,149 @@
+use std::net::IpAddr;
+
+use actix_web::{dev::ServiceRequest, http::header};
+
+/// Returns the IP address of the client which made the request.
+///
+/// If `trust_proxy` is set, the server is assumed to be behind exactly one reverse proxy, and the
+/// address is taken from the last entry the proxy added to the `Forwarded` or `X-Forwarded-For`
+/// header. Earlier entries are ignored, since they were sent by the client and could be forged.
+/// Otherwise, the headers are ignored entirely and the address of the socket peer is used.
+pub fn client_ip(req: &ServiceRequest, trust_proxy: bool) -> Option<IpAddr> {
+    let peer_ip = req.peer_addr().map(|addr| addr.ip());
+
+    if !trust_proxy {
+        return peer_ip;
+    }
+
+    forwarded_ip(req)
+        .or_else(|| x_forwarded_for_ip(req))
+        .or(peer_ip)
+}
+
+/// Gets the `for` parameter of the last element of the last `Forwarded` header, as specified by
+/// RFC 7239.
+fn forwarded_ip(req: &ServiceRequest) -> Option<IpAddr> {
+    let forwarded = req.headers()
+        .get_all(header::FORWARDED)
+        .last()?
+        .to_str()
+        .ok()?;
+
+    let element = forwarded.rsplit(',').next()?;
+
+    element
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+        .and_then(|(_, node)| parse_node(node))
+}
+
+/// Gets the last address in the last `X-Forwarded-For` header.
+fn x_forwarded_for_ip(req: &ServiceRequest) -> Option<IpAddr> {
+    req.headers()
+        .get_all("x-forwarded-for")
+        .last()?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()
+        .and_then(parse_node)
+}
+
+/// Parses a node as it appears in a forwarding header. It may be quoted, IPv6 addresses may be
+/// in square brackets, and either kind of address may be followed by a port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+
+    // "[2001:db8::1]:4711" or "[2001:db8::1]"
+    if let Some(bracketed) = node.strip_prefix('[') {
+        let (ip, rest) = bracketed.split_once(']')?;
+        if !(rest.is_empty() || rest.starts_with(':')) {
+            return None;
+        }
+        return ip.parse().ok();
+    }
+
+    // "192.0.2.1:4711"
+    node.rsplit_once(':')
+        .and_then(|(ip, _)| ip.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn request() -> TestRequest {
+        TestRequest::default().peer_addr("192.0.2.1:1234".parse::<SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn uses_peer_address_without_trust_proxy() {
+        let req = request()
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .insert_header((header::FORWARDED, "for=203.0.113.8"))
+            .to_srv_request();
+
+        assert_eq!(client_ip(&req, false), Some(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn uses_last_x_forwarded_for_entry_with_trust_proxy() {
+        // The first entry was sent by the client, so only the one added by the proxy is used
+        let req = request()
+            .insert_header(("x-forwarded-for", "198.51.100.66, 203.0.113.7"))
+            .to_srv_request();
+
+        assert_eq!(client_ip(&req, true), Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn prefers_forwarded_header_with_trust_proxy() {
+        let req = request()
+            .insert_header((header::FORWARDED, "for=198.51.100.66, for=\"[2001:db8::7]:4711\";proto=https"))
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .to_srv_request();
+
+        assert_eq!(client_ip(&req, true), Some(ip("2001:db8::7")));
+    }
+
+    #[test]
+    fn falls_back_to_peer_address_with_trust_proxy() {
+        let req = request()
+            .insert_header(("x-forwarded-for", "unknown"))
+            .to_srv_request();
+
+        assert_eq!(client_ip(&req, true), Some(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn parses_nodes() {
+        assert_eq!(parse_node("192.0.2.1"), Some(ip("192.0.2.1")));
+        assert_eq!(parse_node(" 192.0.2.1:4711 "), Some(ip("192.0.2.1")));
+        assert_eq!(parse_node("\"192.0.2.1:4711\""), Some(ip("192.0.2.1")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("[2001:db8::1]"), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("\"[2001:db8::1]:4711\""), Some(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn rejects_invalid_nodes() {
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+        assert_eq!(parse_node("[2001:db8::1"), None);
+        assert_eq!(parse_node("[2001:db8::1]x"), None);
+        assert_eq!(parse_node(""), None);
+    }
+}
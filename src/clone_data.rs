@@ -44,7 +44,7 @@ impl<T> FromRequest for CloneData<T> where T: Clone + 'static {
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         future::ready(match req.app_data::<Self>() {
             Some(cd) => Ok(cd.clone()),
-            None => Err(ErrorInternalServerError("no PgPool found in app data")),
+            None => Err(ErrorInternalServerError("requested data not found in app data")),
         })
     }
 }
@@ -0,0 +1,128 @@
+use sqlx::postgres::PgPool;
+
+/// The database connection pools used by the server. Handlers which only read from the database
+/// should use [Pools::read], so that their queries can be sent to a read replica if one is
+/// configured.
+#[derive(Clone, Debug)]
+pub struct Pools {
+    primary: PgPool,
+    replica: Option<PgPool>,
+}
+
+impl Pools {
+    pub fn new(primary: PgPool, replica: Option<PgPool>) -> Self {
+        Self {
+            primary,
+            replica,
+        }
+    }
+
+    /// The pool for the primary database, which must be used for any queries which write.
+    pub fn primary(&self) -> &PgPool {
+        &self.primary
+    }
+
+    /// The pool to use for read-only queries. This is the read replica if there is one, otherwise
+    /// it is the primary.
+    pub fn read(&self) -> &PgPool {
+        self.replica.as_ref().unwrap_or(&self.primary)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::env;
+
+    use chrono::{TimeZone, Utc};
+    use sqlx::{Executor, postgres::PgPoolOptions};
+
+    use crate::DB_URL_VAR;
+
+    use super::*;
+
+    pub(crate) async fn connect() -> PgPool {
+        let db_url = env::var(DB_URL_VAR).expect("DATABASE_URL must be set for database tests");
+        PgPool::connect(&db_url).await.unwrap()
+    }
+
+    /// Connects to a schema of its own holding empty copies of the archive's tables, so that a test
+    /// sees the archive as it is before any robots have been added. The schema should be dropped
+    /// with [drop_schema] when the test is done.
+    pub(crate) async fn connect_empty(schema: &'static str) -> PgPool {
+        let pool = connect().await;
+
+        for statement in [
+            format!("DROP SCHEMA IF EXISTS {} CASCADE", schema),
+            format!("CREATE SCHEMA {}", schema),
+            format!("CREATE TABLE {}.robots (LIKE public.robots INCLUDING ALL)", schema),
+            format!("CREATE TABLE {}.past_dailies (LIKE public.past_dailies INCLUDING ALL)", schema),
+            // The copied table would otherwise take its ids from the real table's sequence
+            format!("CREATE SEQUENCE {0}.robots_id_seq OWNED BY {0}.robots.id", schema),
+            format!("ALTER TABLE {0}.robots ALTER COLUMN id SET DEFAULT nextval('{0}.robots_id_seq')", schema),
+        ] {
+            pool.execute(statement.as_str()).await.unwrap();
+        }
+
+        let db_url = env::var(DB_URL_VAR).unwrap();
+
+        PgPoolOptions::new()
+            .after_connect(move |conn| Box::pin(async move {
+                conn.execute(format!("SET search_path TO {}, public", schema).as_str()).await?;
+                Ok(())
+            }))
+            .connect(&db_url)
+            .await
+            .unwrap()
+    }
+
+    pub(crate) async fn drop_schema(schema: &str) {
+        connect().await
+            .execute(format!("DROP SCHEMA {} CASCADE", schema).as_str())
+            .await
+            .unwrap();
+    }
+
+    /// Adds a robot with the given number and name to the database, returning its id. Robots are
+    /// posted a day apart in the order they are added.
+    pub(crate) async fn insert_robot(
+        pool: &PgPool,
+        robot_number: i32,
+        ident: &str,
+        prefix: &str,
+        suffix: &str,
+        body: &str
+    ) -> i32
+    {
+        sqlx::query_scalar(
+            "INSERT INTO robots (robot_number, ident, prefix, suffix, body, tweet_id, tweet_time) \
+            VALUES ($1, $2, $3, $4, $5, 1, $6 + (SELECT COUNT(*) FROM robots) * INTERVAL '1 day') \
+            RETURNING id"
+        )
+        .bind(robot_number)
+        .bind(ident)
+        .bind(prefix)
+        .bind(suffix)
+        .bind(body)
+        .bind(Utc.ymd(2021, 1, 1).and_hms(12, 0, 0))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    fn lazy_pool() -> PgPool {
+        PgPool::connect_lazy("postgres://localhost/small_robots").unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn reads_use_primary_without_replica() {
+        let pools = Pools::new(lazy_pool(), None);
+        assert!(std::ptr::eq(pools.read(), pools.primary()));
+    }
+
+    #[actix_rt::test]
+    async fn reads_use_replica() {
+        let pools = Pools::new(lazy_pool(), Some(lazy_pool()));
+        assert!(!std::ptr::eq(pools.read(), pools.primary()));
+        assert!(std::ptr::eq(pools.read(), pools.replica.as_ref().unwrap()));
+    }
+}
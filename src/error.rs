@@ -1,11 +1,12 @@
 use std::borrow::Cow;
 use std::error;
 use std::fmt;
+use std::net::IpAddr;
 
 use actix_web::{HttpRequest, HttpResponse};
 use actix_web::Responder;
 use actix_web::error::ResponseError;
-use actix_web::http::StatusCode;
+use actix_web::http::{header, StatusCode};
 use log::log;
 
 use crate::page;
@@ -27,6 +28,16 @@ impl SiteReportError {
             err,
         }
     }
+
+    /// Logs the error along with the id of the request which caused it and the address of the
+    /// client which made it. This is done by the request id middleware rather than when the error
+    /// response is built, since the request is not available there.
+    pub fn log(&self, request_id: &str, client_ip: Option<IpAddr>) {
+        match client_ip {
+            Some(client_ip) => log!(self.err.log_level(), "[{}] [{}] {}", request_id, client_ip, self),
+            None => log!(self.err.log_level(), "[{}] [unknown] {}", request_id, self),
+        }
+    }
 }
 
 impl fmt::Display for SiteReportError {
@@ -41,23 +52,43 @@ impl ResponseError for SiteReportError {
     }
 
     fn error_response(&self) -> HttpResponse {
-        log!(self.err.log_level(), "{}", self);
-
         let status = self.status_code();
-        MarkupResponse::new(page::error_page(status), status).into()
+        let mut response: HttpResponse = MarkupResponse::new(page::error_page(status), status).into();
+
+        match self.err {
+            SiteError::Unauthorized => {
+                response.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Basic realm=\"admin\", charset=\"UTF-8\"")
+                );
+            },
+            SiteError::TooManyRequests { retry_after_secs } => {
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    header::HeaderValue::from(retry_after_secs)
+                );
+            },
+            _ => (),
+        }
+
+        response
     }
 }
 
 impl Responder for SiteReportError {
     fn respond_to(self, _: &HttpRequest) -> HttpResponse {
-        self.error_response()
+        // Keep the error attached to the response so that it still gets logged
+        HttpResponse::from_error(self)
     }
 }
 
 #[derive(Debug)]
 pub enum SiteError {
     BadRequest,
+    Unauthorized,
+    Forbidden,
     NotFound,
+    TooManyRequests { retry_after_secs: u64 },
     DatabaseError(Box<sqlx::Error>),
 }
 
@@ -65,7 +96,10 @@ impl fmt::Display for SiteError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             SiteError::BadRequest => write!(f, "bad request"),
+            SiteError::Unauthorized => write!(f, "unauthorized"),
+            SiteError::Forbidden => write!(f, "forbidden"),
             SiteError::NotFound => write!(f, "resource not found"),
+            SiteError::TooManyRequests { retry_after_secs } => write!(f, "too many requests, retry after {}s", retry_after_secs),
             SiteError::DatabaseError(err) => write!(f, "database error: {}", err),
         }
     }
@@ -81,7 +115,10 @@ impl SiteError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             Self::BadRequest => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -89,7 +126,10 @@ impl SiteError {
     fn log_level(&self) -> log::Level {
         match self {
             Self::BadRequest => log::Level::Warn,
+            Self::Unauthorized => log::Level::Warn,
+            Self::Forbidden => log::Level::Warn,
             Self::NotFound => log::Level::Warn,
+            Self::TooManyRequests { .. } => log::Level::Warn,
             Self::DatabaseError(_) => log::Level::Error,
         }
     }
@@ -1,34 +1,83 @@
+mod auth;
+mod base_path;
+mod client_ip;
 mod clone_data;
+mod db;
 mod respond;
+mod request_id;
 mod error;
 mod page;
+mod page_cache;
+mod rate_limit;
 mod services;
 mod robots;
 mod search;
+mod text;
+#[cfg(feature = "tls")]
+mod tls;
 
+use std::borrow::Cow;
 use std::env;
 use std::ffi::OsStr;
 use std::fmt;
 use std::io;
 use std::ops::Add;
+use std::sync::Arc;
+use std::time::Duration;
 
-use actix_web::{self as aw, get, HttpServer, App, web, HttpRequest};
+use actix_web::{self as aw, get, post, HttpServer, App, web, HttpRequest, HttpResponse};
+use actix_web::http::header;
+use actix_web::middleware;
 use actix_files as fs;
+use chrono::{DateTime, Utc};
 use sqlx::postgres::PgPool;
-use maud::{html, PreEscaped};
+use maud::{html, Markup, PreEscaped};
 use serde::Deserialize;
 
+use auth::{AdminCredentials, RequireAdmin};
 use clone_data::CloneData;
+use db::Pools;
 use error::{SiteError, SiteReportError, SiteReportResult, IntoReport};
+use request_id::AssignRequestId;
+use page_cache::PageCache;
+use rate_limit::{RateLimit, RateLimiter};
 use respond::MarkupResponse;
-use robots::{Linkable, Named, Displayable, RobotPreview, RobotFull};
+use robots::{Linkable, Named, Displayable, RobotId, RobotNumber, RobotTextLink, RobotPreview, RobotFull};
+use search::{SearchLanguage, SearchOrder, SearchOutcome, StopWords};
 
 const DEFAULT_BIND_ADDR: &str = "[::1]:8080";
 
 const BIND_ADDR_VAR: &str = "BIND_ADDRESS";
+const TLS_CERT_VAR: &str = "TLS_CERT";
+const TLS_KEY_VAR: &str = "TLS_KEY";
+const TRUST_PROXY_VAR: &str = "TRUST_PROXY";
 const DB_URL_VAR: &str = "DATABASE_URL";
+const DB_REPLICA_URL_VAR: &str = "DATABASE_URL_REPLICA";
 const ARCHIVE_META_NAME_VAR: &str = "SBB_ARCHIVE_META_NAME";
 const ARCHIVE_META_URL_PREFIX_VAR: &str = "SBB_ARCHIVE_META_URL_PREFIX";
+const ARCHIVE_SEARCH_LANGUAGE_VAR: &str = "SBB_ARCHIVE_SEARCH_LANGUAGE";
+const ARCHIVE_SEARCH_STOP_WORDS_VAR: &str = "SBB_ARCHIVE_SEARCH_STOP_WORDS";
+const ARCHIVE_CW_LABEL_VAR: &str = "SBB_ARCHIVE_CW_LABEL";
+const ARCHIVE_CW_REVEAL_VAR: &str = "SBB_ARCHIVE_CW_REVEAL";
+const ARCHIVE_CW_THUMBNAILS_VAR: &str = "SBB_ARCHIVE_CW_THUMBNAILS";
+const ARCHIVE_RELATED_ROBOTS_VAR: &str = "SBB_ARCHIVE_RELATED_ROBOTS";
+const PAGE_CACHE_TTL_VAR: &str = "SBB_ARCHIVE_PAGE_CACHE_SECS";
+const RATE_LIMIT_BURST_VAR: &str = "SBB_ARCHIVE_RATE_LIMIT_BURST";
+const RATE_LIMIT_PER_MINUTE_VAR: &str = "SBB_ARCHIVE_RATE_LIMIT_PER_MINUTE";
+const ARCHIVE_BASE_PATH_VAR: &str = "SBB_ARCHIVE_BASE_PATH";
+const ADMIN_USERNAME_VAR: &str = "SBB_ARCHIVE_ADMIN_USERNAME";
+const ADMIN_PASSWORD_VAR: &str = "SBB_ARCHIVE_ADMIN_PASSWORD";
+
+const NO_ROBOTS_MESSAGE: &str = "There are no robots in the archive yet. Check back soon!";
+
+const OG_DESCRIPTION_MAX_GRAPHEMES: usize = 200;
+
+const DEFAULT_PAGE_CACHE_TTL_SECS: u64 = 60;
+
+const DEFAULT_RATE_LIMIT_BURST: u32 = 20;
+
+const DEFAULT_RELATED_ROBOTS: u32 = 4;
+const MAX_RELATED_ROBOTS: u32 = 24;
 
 const THH_BOOK_URL: &str
     = "https://www.hive.co.uk/Product/Thomas-Heasman-Hunt/Small-Robots--A-collection-of-one-hundred-mostly-useful-robot-friends/24078313";
@@ -41,17 +90,19 @@ const THH_COMMISION_URL: &str
 
 #[derive(Debug)]
 enum ServerError {
-    DbError(Box<sqlx::Error>),
-    IoError(Box<io::Error>),
-    EnvError(Box<env::VarError>),
+    Db(Box<sqlx::Error>),
+    Io(Box<io::Error>),
+    Env(Box<env::VarError>),
+    Config(String),
 }
 
 impl fmt::Display for ServerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::DbError(err) => err.fmt(f),
-            Self::IoError(err) => err.fmt(f),
-            Self::EnvError(err) => err.fmt(f),
+            Self::Db(err) => err.fmt(f),
+            Self::Io(err) => err.fmt(f),
+            Self::Env(err) => err.fmt(f),
+            Self::Config(msg) => write!(f, "invalid configuration: {}", msg),
         }
     }
 }
@@ -60,36 +111,41 @@ impl std::error::Error for ServerError {}
 
 impl From<sqlx::Error> for ServerError {
     fn from(err: sqlx::Error) -> Self {
-        Self::DbError(Box::new(err))
+        Self::Db(Box::new(err))
     }
 }
 
 impl From<io::Error> for ServerError {
     fn from(err: io::Error) -> Self {
-        Self::IoError(Box::new(err))
+        Self::Io(Box::new(err))
     }
 }
 
 impl From<env::VarError> for ServerError {
     fn from(err: env::VarError) -> Self {
-        Self::EnvError(Box::new(err))
+        Self::Env(Box::new(err))
     }
 }
 
 #[get("/")]
-async fn landing_page(pool: CloneData<PgPool>) -> SiteReportResult<MarkupResponse> {
-    let latest: Vec<RobotPreview> = sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_thumb_path, \
-            alt, custom_alt \
+async fn landing_page(
+    meta: web::Data<InstanceMeta>,
+    pools: CloneData<Pools>
+) -> SiteReportResult<MarkupResponse>
+{
+    let latest: Vec<RobotPreview> = sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
         ORDER BY tweet_time DESC \
-        LIMIT 20"
-    )
-    .fetch_all(&*pool)
+        LIMIT 20",
+        RobotPreview::COLUMNS
+    ))
+    .fetch_all(pools.read())
     .await
     .map_err(|err| err.into_report("failed to get latest robots"))?;
 
+    let now = Utc::now();
+
     Ok(MarkupResponse::ok(page::archive_page(
         "Small Robots Archive",
         html! {
@@ -117,39 +173,41 @@ async fn landing_page(pool: CloneData<PgPool>) -> SiteReportResult<MarkupRespons
 
             div class="section" {
                 h2 { "Recent robots" }
-                ul class="robots_row" {
-                    @for robot in &latest {
-                        li class="robot_container" {
-                            a href=(robot.page_link()) class="link_area" {
-                                @if let Some(image_resource_url) = robot.image_resource_url() {
-                                    img
-                                        src=(image_resource_url)
-                                        alt=(robot.image_alt())
-                                        draggable="false";
-                                } @else {
-                                    img alt="Image not found";
-                                }
-                                h3 { (robot.full_name()) }
-                                h3 class="robot_number" { "#"(robot.robot_number) }
-                            }
+                @if latest.is_empty() {
+                    p { (NO_ROBOTS_MESSAGE) }
+                } @else {
+                    ul class="robots_row" {
+                        @for robot in &latest {
+                            (robot_card(&meta, robot, Some(now)))
                         }
                     }
-                }
 
-                p {
-                    a class="link_text" href="/all" { "See all robots" }
+                    p {
+                        a class="link_text" href=(base_path::link("/all")) { "See all robots" }
+                    }
                 }
             }
         }
     )))
 }
 
-//TODO: render content warnings
-async fn render_all_robots(pool: PgPool, page: u32) -> SiteReportResult<MarkupResponse> {
+async fn render_all_robots(
+    meta: &InstanceMeta,
+    cache: &PageCache,
+    pool: &PgPool,
+    page: u32
+) -> SiteReportResult<MarkupResponse>
+{
     const PAGE_SIZE: u32 = 48;
 
+    let cache_key = format!("all/{}", page);
+
+    if let Some(markup) = cache.get(&cache_key) {
+        return Ok(MarkupResponse::ok(markup));
+    }
+
     let num_robots: robots::Count = sqlx::query_as("SELECT COUNT(*) AS count FROM robots")
-        .fetch_one(&pool)
+        .fetch_one(pool)
         .await
         .map_err(|err| err.into_report("failed to count rows in robots table"))?;
 
@@ -158,29 +216,60 @@ async fn render_all_robots(pool: PgPool, page: u32) -> SiteReportResult<MarkupRe
     let limit = PAGE_SIZE as i64;
     let offset = (PAGE_SIZE * page) as i64;
 
-    let robots: Vec<RobotPreview> = sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_thumb_path, \
-            alt, custom_alt \
+    let robots: Vec<RobotPreview> = sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
         ORDER BY robot_number, id \
         LIMIT $1 \
-        OFFSET $2"
-    )
+        OFFSET $2",
+        RobotPreview::COLUMNS
+    ))
     .bind(limit)
     .bind(offset)
-    .fetch_all(&pool)
+    .fetch_all(pool)
     .await
     .map_err(|err| err.into_report(format!("failed to get robots page {}", page)))?;
 
     let pagination = Pagination::try_new(page, num_pages);
 
-    let pagination_menu = pagination.map(|pagination| html! {
+    let pagination_menu = pagination.map(render_pagination);
+
+    let markup = page::archive_page(
+        "All robots",
+        html! {
+            div class="section" {
+                h2 { "All robots" }
+                ul class="robots_grid" {
+                    @for robot in &robots {
+                        (robot_card(meta, robot, None))
+                    }
+                }
+            }
+
+            @if let Some(pagination_menu) = pagination_menu {
+                div class="section" {
+                    (pagination_menu)
+                }
+            }
+        }
+    );
+
+    // Pages past the end are not cached, so that they cannot be used to fill up the cache
+    if page < num_pages {
+        cache.insert(cache_key, markup.clone());
+    }
+
+    Ok(MarkupResponse::ok(markup))
+}
+
+/// Renders the menu for moving between pages of the list of all robots.
+fn render_pagination(pagination: Pagination) -> Markup {
+    html! {
         nav class="pagination" {
             ul {
                 li class="pagination_item_major" {
                     @if let Some(prev_page) = pagination.prev_page {
-                        a class="pagination_number_other" href=(format!("/all/{}", prev_page.add(1))) { "Previous" }
+                        a class="pagination_number_other" href=(format!("{}/all/{}", base_path::get(), prev_page.add(1))) { "Previous" }
                     } @else {
                         span class="pagination_disabled no_select" { "Previous" }
                     }
@@ -188,7 +277,7 @@ async fn render_all_robots(pool: PgPool, page: u32) -> SiteReportResult<MarkupRe
 
                 @if let Some(first_page) = pagination.first_page {
                     li class="pagination_item_minor" {
-                        a class="pagination_number_other" href=(format!("/all/{}", first_page.add(1))) { (first_page.add(1)) }
+                        a class="pagination_number_other" href=(format!("{}/all/{}", base_path::get(), first_page.add(1))) { (first_page.add(1)) }
                     }
 
                     li class="pagination_item_minor" {
@@ -198,7 +287,7 @@ async fn render_all_robots(pool: PgPool, page: u32) -> SiteReportResult<MarkupRe
 
                 @for n in pagination.min_range_page .. pagination.current_page {
                     li class="pagination_item_minor" {
-                        a class="pagination_number_other" href=(format!("/all/{}", n.add(1))) { (n.add(1)) }
+                        a class="pagination_number_other" href=(format!("{}/all/{}", base_path::get(), n.add(1))) { (n.add(1)) }
                     }
                 }
 
@@ -208,7 +297,7 @@ async fn render_all_robots(pool: PgPool, page: u32) -> SiteReportResult<MarkupRe
 
                 @for n in (pagination.current_page ..= pagination.max_range_page).skip(1) {
                     li class="pagination_item_minor" {
-                        a class="pagination_number_other" href=(format!("/all/{}", n.add(1))) { (n.add(1)) }
+                        a class="pagination_number_other" href=(format!("{}/all/{}", base_path::get(), n.add(1))) { (n.add(1)) }
                     }
                 }
 
@@ -218,105 +307,112 @@ async fn render_all_robots(pool: PgPool, page: u32) -> SiteReportResult<MarkupRe
                     }
 
                     li class="pagination_item_minor" {
-                        a class="pagination_number_other" href=(format!("/all/{}", last_page.add(1))) { (last_page.add(1)) }
+                        a class="pagination_number_other" href=(format!("{}/all/{}", base_path::get(), last_page.add(1))) { (last_page.add(1)) }
                     }
                 }
 
                 li class="pagination_item_major" {
                     @if let Some(next_page) = pagination.next_page {
-                        a class="pagination_number_other" href=(format!("/all/{}", next_page.add(1))) { "Next" }
+                        a class="pagination_number_other" href=(format!("{}/all/{}", base_path::get(), next_page.add(1))) { "Next" }
                     }  @else {
                         span class="pagination_disabled no_select" { "Next" }
                     }
                 }
             }
         }
-    });
-
-    Ok(MarkupResponse::ok(page::archive_page(
-        "All robots",
-        html! {
-            div class="section" {
-                h2 { "All robots" }
-                ul class="robots_grid" {
-                    @for robot in &robots {
-                        li class="robot_container" {
-                            a href=(robot.page_link()) class="link_area" {
-                                @if let Some(image_resource_url) = robot.image_resource_url() {
-                                    img
-                                        src=(image_resource_url)
-                                        alt=(robot.image_alt())
-                                        draggable="false";
-                                } @else {
-                                    img alt="Image not found";
-                                }
-                                h3 { (robot.full_name()) }
-                                h3 class="robot_number" { "#"(robot.robot_number) }
-                            }
-                        }
-                    }
-                }
-            }
-
-            @if let Some(pagination_menu) = pagination_menu {
-                div class="section" {
-                    (pagination_menu)
-                }
-            }
-        }
-    )))
+    }
 }
 
 #[get("/all")]
-async fn all_robots(pool: CloneData<PgPool>) -> SiteReportResult<MarkupResponse> {
-    render_all_robots(pool.inner, 0).await
+async fn all_robots(
+    meta: web::Data<InstanceMeta>,
+    cache: web::Data<PageCache>,
+    pools: CloneData<Pools>
+) -> SiteReportResult<MarkupResponse>
+{
+    render_all_robots(&meta, &cache, pools.read(), 0).await
 }
 
 #[get("/all/{page}")]
-async fn all_robots_paged(pool: CloneData<PgPool>, page: web::Path<u32>) -> SiteReportResult<MarkupResponse> {
+async fn all_robots_paged(
+    meta: web::Data<InstanceMeta>,
+    cache: web::Data<PageCache>,
+    pools: CloneData<Pools>,
+    page: web::Path<u32>
+) -> SiteReportResult<MarkupResponse>
+{
     let page = page.into_inner();
 
     let page = page
         .checked_sub(1)
         .ok_or_else(|| SiteError::BadRequest.report(format!("invalid page number {}", page)))?;
 
-    render_all_robots(pool.inner, page).await
+    render_all_robots(&meta, &cache, pools.read(), page).await
 }
 
 #[derive(Deserialize)]
 struct SearchQuery {
     query: String,
+    #[serde(default)]
+    order: SearchOrder,
 }
 
-#[get("/search")]
-async fn search_robots(pool: CloneData<PgPool>, query: web::Query<SearchQuery>) -> SiteReportResult<MarkupResponse> {
+/// Registered at `/search` in `main`, so that it can be rate limited.
+async fn search_robots(
+    meta: web::Data<InstanceMeta>,
+    pools: CloneData<Pools>,
+    query: web::Query<SearchQuery>
+) -> SiteReportResult<MarkupResponse>
+{
     const MAX_QUERY_CHARS: usize = 64;
 
     let search_query = query.query.chars().take(MAX_QUERY_CHARS).collect::<String>();
 
-    let robots = search::search(&*pool, &search_query).await?;
+    let outcome = search::search(
+        pools.read(),
+        &search_query,
+        meta.search_language,
+        &meta.stop_words,
+        query.order
+    ).await?;
+
+    let robots = match outcome {
+        SearchOutcome::NoQuery => {
+            return Ok(MarkupResponse::ok(page::archive_page(
+                "Search",
+                html! {
+                    div class="section" {
+                        h2 { "Search" }
+                        p { "Type something in the search bar to look for robots by name, number or description." }
+                    }
+                }
+            )));
+        },
+        SearchOutcome::Results(robots) => robots,
+    };
 
     Ok(MarkupResponse::ok(page::archive_page(
         "All robots",
         html! {
             div class="section" {
-                h2 class="word_break" { "Search results for \"" (search_query) "\"" }
+                (page::search_summary(&search_query, robots.len()))
+                @if robots.is_empty() {
+                    p { "Try searching for something else!" }
+                } @else {
+                    form class="search_order" method="get" action=(base_path::link("/search")) {
+                        input type="hidden" name="query" value=(search_query);
+                        "Sort by "
+                        button type="submit" name="order" value="relevance" disabled[query.order == SearchOrder::Relevance] {
+                            "relevance"
+                        }
+                        button type="submit" name="order" value="number" disabled[query.order == SearchOrder::Number] {
+                            "number"
+                        }
+                    }
+                }
                 ul class="robots_grid" {
                     @for robot in &robots {
-                        li class="robot_container" {
-                            a href=(robot.page_link()) class="link_area" {
-                                @if let Some(image_resource_url) = robot.image_resource_url() {
-                                    img
-                                        src=(image_resource_url)
-                                        alt=(robot.image_alt())
-                                        draggable="false";
-                                } @else {
-                                    img alt="Image not found";
-                                }
-                                h3 { (robot.full_name()) }
-                                h3 class="robot_number" { "#"(robot.robot_number) }
-                            }
-                        }
+                        (robot_card(&meta, robot, None))
                     }
                 }
             }
@@ -324,18 +420,125 @@ async fn search_robots(pool: CloneData<PgPool>, query: web::Query<SearchQuery>)
     )))
 }
 
-fn render_robot(meta: &InstanceMeta, robot: RobotFull) -> MarkupResponse {
+/// Renders a robot as a card for a list of robots. If `now` is given, the card says how long ago
+/// the robot was posted.
+fn robot_card(meta: &InstanceMeta, robot: &RobotPreview, now: Option<DateTime<Utc>>) -> Markup {
+    html! {
+        li class="robot_container" {
+            a href=(robot.page_link()) class="link_area" {
+                @match (robot.image_resource_url(), robot.content_warning.as_deref(), meta.cw_thumbnails) {
+                    (Some(image_resource_url), Some(_), CwThumbnails::Blur) => {
+                        img
+                            class="robot_image_cw"
+                            src=(image_resource_url)
+                            alt=(robot.image_alt())
+                            draggable="false";
+                    },
+                    (Some(_), Some(content_warning), CwThumbnails::Hide) => {
+                        img alt=(format!("{}: {}", meta.cw_label, content_warning));
+                    },
+                    (Some(image_resource_url), None, _) => {
+                        img
+                            src=(image_resource_url)
+                            alt=(robot.image_alt())
+                            draggable="false";
+                    },
+                    (None, _, _) => {
+                        img alt="Image not found";
+                    },
+                }
+                h3 { (robot_name(robot)) }
+                h3 class="robot_number" { "#"(robot.robot_number) }
+                @if let Some(content_warning) = robot.content_warning.as_deref() {
+                    p class="robot_cw" { (meta.cw_label) ": " (content_warning) }
+                }
+                @if let Some(now) = now {
+                    p class="robot_time" { (text::relative_time(robot.tweet_time, now)) }
+                }
+            }
+        }
+    }
+}
+
+/// Renders a robot's name with its suffix in a separate span, so that it can be styled.
+fn robot_name<R: Named>(robot: &R) -> Markup {
+    let (prefix, suffix) = robot.full_name_parts();
+
+    html! {
+        (prefix) span class="robot_suffix" { (suffix) }
+    }
+}
+
+/// Renders a page explaining that there is no robot to show, for when the archive is empty.
+fn render_no_robots(title: &str, message: &str) -> MarkupResponse {
+    MarkupResponse::ok(page::archive_page(
+        title,
+        html! {
+            div class="section" {
+                h2 { (title) }
+                p { (message) }
+            }
+        }
+    ))
+}
+
+/// Renders the page for a single robot. If `another_random` is given, the page links to it as a
+/// way to see another random robot. Any `related` robots are shown below the robot itself.
+fn render_robot(
+    meta: &InstanceMeta,
+    robot: RobotFull,
+    related: &[RobotPreview],
+    another_random: Option<&str>
+) -> MarkupResponse
+{
     let full_name = robot.full_name();
 
     let tweet_link = format!("https://twitter.com/smolrobots/status/{}", robot.tweet_id);
 
     let permalink = meta.url_prefix
         .as_deref()
-        .map(|prefix| format!("{}/robot/{}/{}", prefix, robot.robot_number, robot.ident));
+        .map(|prefix| format!("{}{}", prefix, robot.page_link()));
+
+    let short_link = match meta.url_prefix.as_deref() {
+        Some(prefix) => format!("{}{}", prefix, robot.short_link()),
+        None => robot.short_link(),
+    };
+
+    // Description used when the robot's page is embedded in other sites
+    // Robots with a content warning are described by the warning rather than the body, so that
+    // link previews do not show what the warning is hiding
+    let description = match robot.content_warning.as_deref() {
+        Some(content_warning) => Cow::Owned(format!("{}: {}", meta.cw_label, content_warning)),
+        None => text::truncate_graphemes(&robot.body, OG_DESCRIPTION_MAX_GRAPHEMES),
+    };
+
+    // OpenGraph requires absolute URLs, so the image can only be included if we know the URL the
+    // site is served from. Images behind a content warning are left out, since link previews
+    // would otherwise show them without the warning
+    let og_image_url = meta.url_prefix
+        .as_deref()
+        .filter(|_| robot.content_warning.is_none())
+        .and_then(|prefix| robot.image_resource_url_absolute(prefix));
+
+    let page_head = html! {
+        meta name="description" content=(description);
+        meta property="og:type" content="article";
+        meta property="og:title" content=(full_name);
+        meta property="og:description" content=(description);
+        @if let Some(permalink) = &permalink {
+            meta property="og:url" content=(permalink);
+        }
+        @if let Some(image_url) = &og_image_url {
+            meta property="og:image" content=(image_url);
+            meta property="og:image:alt" content=(robot.image_alt());
+        }
+    };
+
+    let image_resource_url = robot.image_resource_url();
 
     let robot_content = html! {
         div class="robot_content" {
-            @if let Some(image_resource_url) = robot.image_resource_url() {
+            @if let Some(image_resource_url) = &image_resource_url {
                 div class="robot_image_full_container" {
                     a href=(tweet_link) {
                         img
@@ -352,6 +555,21 @@ fn render_robot(meta: &InstanceMeta, robot: RobotFull) -> MarkupResponse {
                     (robot.body)
                 }
 
+                @if image_resource_url.is_some() {
+                    details class="robot_alt" {
+                        summary { "Image description" }
+                        p { (robot.image_alt()) }
+                    }
+                }
+
+                p {
+                    "Posted on "
+                    time datetime=(robot.tweet_time.to_rfc3339()) {
+                        (robot.tweet_time.format("%-d %B %Y"))
+                    }
+                    " (" (text::relative_time(robot.tweet_time, Utc::now())) ")"
+                }
+
                 p {
                     a class="link_text" href=(tweet_link) { "Go to original Tweet" }
                 }
@@ -361,22 +579,33 @@ fn render_robot(meta: &InstanceMeta, robot: RobotFull) -> MarkupResponse {
                         "Permalink: " a class="link_text" href=(permalink) { (permalink) }
                     }
                 }
+
+                p {
+                    "Short link: " a class="link_text" href=(short_link) { (short_link) }
+                }
+
+                @if let Some(another_random) = another_random {
+                    p {
+                        a class="link_text" href=(another_random) { "Show me another random robot" }
+                    }
+                }
             }
         }
     };
 
-    MarkupResponse::ok(page::archive_page(
+    MarkupResponse::ok(page::archive_page_with_head(
         &full_name,
+        page_head,
         html! {
             div class="section" {
                 h2 class="robot_title word_break" {
-                    span class="robot_number" { "#" (robot.robot_number) } " " (full_name)
+                    span class="robot_number" { "#" (robot.robot_number) } " " (robot_name(&robot))
                 }
 
                 @match robot.content_warning.as_deref() {
                     Some(content_warning) => {
                         details {
-                            summary { "(Click to expand) Content warning: " (content_warning) }
+                            summary { (meta.cw_reveal) " " (meta.cw_label) ": " (content_warning) }
                             (robot_content)
                         }
                     }
@@ -386,77 +615,253 @@ fn render_robot(meta: &InstanceMeta, robot: RobotFull) -> MarkupResponse {
                     }
                 }
             }
+
+            @if !related.is_empty() {
+                div class="section" {
+                    h2 { "You might also like" }
+                    ul class="robots_row" {
+                        @for related_robot in related {
+                            (robot_card(meta, related_robot, None))
+                        }
+                    }
+                }
+            }
         }
     ))
 }
 
+/// Gets the robots to show in the "you might also like" section of the given robot's page.
+async fn fetch_related_robots(
+    meta: &InstanceMeta,
+    pool: &PgPool,
+    robot: &RobotFull
+) -> SiteReportResult<Vec<RobotPreview>>
+{
+    if meta.related_robots == 0 {
+        return Ok(Vec::new());
+    }
+
+    search::related(pool, robot.id, &robot.ident, meta.related_robots as i32)
+        .await
+        .map_err(|err| err.into_report(format!("failed to get robots related to {}", robot.id)))
+}
+
+/// The page for a single robot. The robot can be identified either by its ident or by the slug of
+/// its name; slugs redirect to the canonical ident URL.
 #[get("/robot/{number}/{ident}")]
 async fn robot_page(
     meta: web::Data<InstanceMeta>,
-    pool: CloneData<PgPool>,
-    path: web::Path<(i32, String)>
-) -> SiteReportResult<MarkupResponse>
+    pools: CloneData<Pools>,
+    path: web::Path<(RobotNumber, String)>
+) -> SiteReportResult<HttpResponse>
 {
     let (number, ident) = path.into_inner();
 
-    let robot: RobotFull = sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_path, \
-            alt, custom_alt, body, tweet_id \
+    let robot: Option<RobotFull> = sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
-        WHERE (robot_number, ident) = ($1, $2)"
-    )
+        WHERE (robot_number, ident) = ($1, $2)",
+        RobotFull::COLUMNS
+    ))
     .bind(number)
     .bind(&ident)
-    .fetch_optional(&*pool)
+    .fetch_optional(pools.read())
+    .await
+    .map_err(|err| err.into_report(format!("failed to get robot {}/{}", number, ident)))?;
+
+    if let Some(robot) = robot {
+        let related = fetch_related_robots(&meta, pools.read(), &robot).await?;
+        return Ok(render_robot(&meta, robot, &related, None).into());
+    }
+
+    // The path may contain a slug rather than an ident. There are very few robots with any given
+    // number, so they are compared against the slug here rather than in the query
+    let same_number: Vec<RobotTextLink> = sqlx::query_as(&format!(
+        "SELECT {} \
+        FROM robots \
+        WHERE robot_number = $1",
+        RobotTextLink::COLUMNS
+    ))
+    .bind(number)
+    .fetch_all(pools.read())
+    .await
+    .map_err(|err| err.into_report(format!("failed to get robots numbered {}", number)))?;
+
+    let robot = find_by_slug(&same_number, &ident)
+        .ok_or_else(|| SiteError::NotFound.report(format!("failed to get robot {}/{}", number, ident)))?;
+
+    Ok(HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, robot.page_link()))
+        .finish())
+}
+
+/// Finds the robot whose name gives the slug, out of robots which share a number.
+fn find_by_slug<'a, R: Named>(robots: &'a [R], slug: &str) -> Option<&'a R> {
+    robots
+        .iter()
+        .find(|robot| robot.slug() == slug)
+}
+
+/// Redirects to the page of the robot with the given number. If several robots share the
+/// number, the one which was added first is used.
+#[get("/r/{number}")]
+async fn robot_short_link(
+    pools: CloneData<Pools>,
+    number: web::Path<RobotNumber>
+) -> SiteReportResult<HttpResponse>
+{
+    let number = number.into_inner();
+
+    let robot: RobotTextLink = sqlx::query_as(&format!(
+        "SELECT {} \
+        FROM robots \
+        WHERE robot_number = $1 \
+        ORDER BY id \
+        LIMIT 1",
+        RobotTextLink::COLUMNS
+    ))
+    .bind(number)
+    .fetch_optional(pools.read())
     .await
     .map_err(SiteError::from)
     .and_then(|robot| robot.ok_or(SiteError::NotFound))
-    .map_err(|err| err.report(format!("failed to get robot {}/{}", number, ident)))?;
+    .map_err(|err| err.report(format!("failed to get robot {}", number)))?;
 
-    Ok(render_robot(&meta, robot))
+    Ok(HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, robot.page_link()))
+        .finish())
 }
 
 #[get("/daily")]
 async fn daily_robot(
     meta: web::Data<InstanceMeta>,
-    pool: CloneData<PgPool>
+    pools: CloneData<Pools>
 ) -> SiteReportResult<MarkupResponse>
 {
-    let robot: RobotFull = sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_path, \
-            alt, custom_alt, body, tweet_id \
+    let robot: Option<RobotFull> = sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
         WHERE id IN (SELECT robot_id FROM past_dailies ORDER BY posted_on DESC LIMIT 1) \
         LIMIT 1",
-    )
-    .fetch_one(&*pool)
+        RobotFull::COLUMNS
+    ))
+    .fetch_optional(pools.read())
     .await
     .map_err(|err| err.into_report("failed to get daily robot"))?;
 
-    Ok(render_robot(&meta, robot))
+    let robot = match robot {
+        Some(robot) => robot,
+        None => return Ok(render_no_robots("Robot of the day", "No robot of the day has been chosen yet. Check back soon!")),
+    };
+
+    let related = fetch_related_robots(&meta, pools.read(), &robot).await?;
+
+    Ok(render_robot(&meta, robot, &related, None))
+}
+
+#[derive(Deserialize)]
+struct RandomQuery {
+    /// Comma-separated ids of robots which should not be chosen, such as the robots the user was
+    /// most recently shown.
+    exclude: Option<String>,
+}
+
+/// The robots which `/random` should not choose. Only the most recent ids are kept, so the list
+/// cannot grow without bound as the user keeps asking for another random robot.
+#[derive(Default, Debug)]
+struct Exclusions(Vec<RobotId>);
+
+impl Exclusions {
+    const MAX_LEN: usize = 16;
+
+    /// Parses a comma-separated list of robot ids, oldest first. Entries which are not ids are
+    /// ignored.
+    fn from_list(list: &str) -> Self {
+        let mut ids = list
+            .split(',')
+            .filter_map(|id| id.trim().parse::<i32>().ok().map(RobotId))
+            .collect::<Vec<_>>();
+
+        if ids.len() > Self::MAX_LEN {
+            ids.drain(..ids.len() - Self::MAX_LEN);
+        }
+
+        Exclusions(ids)
+    }
+
+    /// Adds the id of the robot shown most recently, forgetting the oldest id if the list is full.
+    fn push(&mut self, id: RobotId) {
+        self.0.push(id);
+        if self.0.len() > Self::MAX_LEN {
+            self.0.remove(0);
+        }
+    }
+
+    fn ids(&self) -> &[RobotId] {
+        &self.0
+    }
 }
 
-#[get("/random")]
+/// Formats the ids as a comma-separated list, as accepted by [Exclusions::from_list].
+impl fmt::Display for Exclusions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, id) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            id.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Registered at `/random` in `main`, so that it can be rate limited.
 async fn random_robot(
     meta: web::Data<InstanceMeta>,
-    pool: CloneData<PgPool>
+    pools: CloneData<Pools>,
+    query: web::Query<RandomQuery>
 ) -> SiteReportResult<MarkupResponse>
 {
-    let robot: RobotFull = sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_path, \
-            alt, custom_alt, body, tweet_id \
+    let mut exclude = query.exclude
+        .as_deref()
+        .map(Exclusions::from_list)
+        .unwrap_or_default();
+
+    let robot = match fetch_random_robot(pools.read(), exclude.ids()).await {
+        Ok(Some(robot)) => Ok(Some(robot)),
+        // If every robot was excluded, ignore the exclusions rather than showing nothing
+        Ok(None) => fetch_random_robot(pools.read(), &[]).await,
+        Err(err) => Err(err),
+    }
+    .map_err(|err| err.into_report("failed to get random robot"))?;
+
+    let robot = match robot {
+        Some(robot) => robot,
+        None => return Ok(render_no_robots("Random robot", NO_ROBOTS_MESSAGE)),
+    };
+
+    // Link to another random robot, excluding this one and the ones shown before it
+    exclude.push(robot.id);
+
+    let another_random = format!("{}/random?exclude={}", base_path::get(), exclude);
+
+    let related = fetch_related_robots(&meta, pools.read(), &robot).await?;
+
+    Ok(render_robot(&meta, robot, &related, Some(&another_random)))
+}
+
+async fn fetch_random_robot(pool: &PgPool, exclude: &[RobotId]) -> sqlx::Result<Option<RobotFull>> {
+    sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
+        WHERE id <> ALL($1) \
         LIMIT 1 \
-        OFFSET FLOOR(RANDOM() * (SELECT COUNT (*) FROM robots))",
-    )
-    .fetch_one(&*pool)
+        OFFSET FLOOR(RANDOM() * (SELECT COUNT (*) FROM robots WHERE id <> ALL($1)))",
+        RobotFull::COLUMNS
+    ))
+    .bind(exclude.iter().map(|id| id.0).collect::<Vec<_>>())
+    .fetch_optional(pool)
     .await
-    .map_err(|err| err.into_report("failed to get random robot"))?;
-
-    Ok(render_robot(&meta, robot))
 }
 
 #[get("/about")]
@@ -518,12 +923,86 @@ async fn about_page(meta: web::Data<InstanceMeta>) -> MarkupResponse {
                 h2 id="instance" { "Instance info" }
                 ul {
                     li { "Instance name: " (instance_name) }
+                    li { "Search language: " (meta.search_language.name()) }
                 }
             }
         }
     ))
 }
 
+#[derive(Deserialize)]
+struct AltForm {
+    alt: String,
+}
+
+#[post("/robots/{id}/alt")]
+async fn admin_set_alt(
+    cache: web::Data<PageCache>,
+    pools: CloneData<Pools>,
+    id: web::Path<RobotId>,
+    form: web::Form<AltForm>
+) -> SiteReportResult<HttpResponse>
+{
+    const MAX_ALT_CHARS: usize = 1000;
+
+    let id = id.into_inner();
+
+    // The alt text is stored as plain text rather than HTML; it is escaped by maud when it is
+    // rendered into an `alt` attribute, so it must not be escaped here as well.
+    let alt = form.alt.trim();
+
+    if alt.chars().count() > MAX_ALT_CHARS {
+        return Err(SiteError::BadRequest.report(format!("alt text for robot {} too long", id)));
+    }
+
+    // Submitting empty alt text clears the custom alt, so the original alt is used again
+    let custom_alt = if alt.is_empty() { None } else { Some(alt) };
+
+    let robot: RobotTextLink = sqlx::query_as(&format!(
+        "UPDATE robots \
+        SET custom_alt = $1 \
+        WHERE id = $2 \
+        RETURNING {}",
+        RobotTextLink::COLUMNS
+    ))
+    .bind(custom_alt)
+    .bind(id)
+    .fetch_optional(pools.primary())
+    .await
+    .map_err(SiteError::from)
+    .and_then(|robot| robot.ok_or(SiteError::NotFound))
+    .map_err(|err| err.report(format!("failed to set alt text for robot {}", id)))?;
+
+    // The alt text appears on cached pages of robots, so they must be rendered again
+    cache.clear();
+
+    Ok(HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, robot.page_link()))
+        .finish())
+}
+
+/// Crawlers only look for robots.txt at the root of the domain, so when the archive is served
+/// under a base path this is served at e.g. /prefix/robots.txt, where they will never find it. In
+/// that case, the rules must be copied into the robots.txt served at the root by whatever serves
+/// the rest of the domain.
+#[get("/robots.txt")]
+async fn robots_txt() -> HttpResponse {
+    // Crawlers are kept away from pages whose content is different on every request, and from
+    // pages which are not meant to be public
+    let robots_txt = format!(
+        "User-agent: *\n\
+        Allow: {0}/\n\
+        Disallow: {0}/random\n\
+        Disallow: {0}/search\n\
+        Disallow: {0}/admin/\n",
+        base_path::get()
+    );
+
+    HttpResponse::Ok()
+        .content_type(mime::TEXT_PLAIN_UTF_8)
+        .body(robots_txt)
+}
+
 #[get("/bootstrap/ids")]
 async fn bootstrap_ids() -> aw::Result<fs::NamedFile> {
     fs::NamedFile::open("./generated/bootstrap/ids")
@@ -538,6 +1017,12 @@ async fn bootstrap_alt() -> aw::Result<fs::NamedFile> {
         .map(|f| f.set_content_type(mime::APPLICATION_JSON))
 }
 
+async fn base_path_redirect() -> HttpResponse {
+    HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, base_path::link("/")))
+        .finish()
+}
+
 async fn not_found(req: HttpRequest) -> SiteReportError {
     SiteError::NotFound
         .report(req.path().to_owned())
@@ -611,15 +1096,86 @@ struct InstanceMeta {
     /// A name for this specific instance of the archive.
     name: Option<String>,
 
-    /// The scheme to use for permalinks.
+    /// The scheme to use for permalinks. This should not include the base path, which is added to
+    /// links separately.
     url_prefix: Option<String>,
+
+    /// The Postgres text search configuration to use for full text search.
+    search_language: SearchLanguage,
+
+    /// Words which are ignored when matching search queries against robot idents.
+    stop_words: StopWords,
+
+    /// The label shown before a robot's content warning.
+    cw_label: String,
+
+    /// The text prompting the user to reveal a robot hidden behind a content warning.
+    cw_reveal: String,
+
+    /// How thumbnails of robots with content warnings are shown in lists of robots.
+    cw_thumbnails: CwThumbnails,
+
+    /// The maximum number of related robots shown on a robot's page. Zero disables the section.
+    related_robots: u32,
+}
+
+/// How to show the thumbnail of a robot with a content warning.
+#[derive(Copy, Clone, Debug)]
+enum CwThumbnails {
+    /// Show the thumbnail blurred.
+    Blur,
+    /// Do not show the thumbnail at all.
+    Hide,
+}
+
+impl CwThumbnails {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blur" => Some(Self::Blur),
+            "hide" => Some(Self::Hide),
+            _ => None,
+        }
+    }
 }
 
 impl InstanceMeta {
     fn new_env() -> Result<InstanceMeta, ServerError> {
+        let search_language = match env_var_opt(ARCHIVE_SEARCH_LANGUAGE_VAR)? {
+            Some(name) => SearchLanguage::from_name(&name)
+                .ok_or_else(|| ServerError::Config(format!("unknown search language {:?}", name)))?,
+            None => SearchLanguage::default(),
+        };
+
+        let cw_thumbnails = match env_var_opt(ARCHIVE_CW_THUMBNAILS_VAR)? {
+            Some(name) => CwThumbnails::from_name(&name)
+                .ok_or_else(|| ServerError::Config(format!("unknown content warning thumbnail style {:?}", name)))?,
+            None => CwThumbnails::Blur,
+        };
+
+        let related_robots = match env_var_opt(ARCHIVE_RELATED_ROBOTS_VAR)? {
+            Some(n) => n.parse::<u32>()
+                .ok()
+                .filter(|&n| n <= MAX_RELATED_ROBOTS)
+                .ok_or_else(|| ServerError::Config(format!(
+                    "number of related robots must be between 0 and {}, got {:?}", MAX_RELATED_ROBOTS, n
+                )))?,
+            None => DEFAULT_RELATED_ROBOTS,
+        };
+
         Ok(InstanceMeta {
             name: env_var_opt(ARCHIVE_META_NAME_VAR)?,
-            url_prefix: env_var_opt(ARCHIVE_META_URL_PREFIX_VAR)?,
+            url_prefix: env_var_opt(ARCHIVE_META_URL_PREFIX_VAR)?
+                .map(|prefix| prefix.trim_end_matches('/').to_owned()),
+            search_language,
+            stop_words: env_var_opt(ARCHIVE_SEARCH_STOP_WORDS_VAR)?
+                .map(|list| StopWords::from_list(&list))
+                .unwrap_or_default(),
+            cw_label: env_var_opt(ARCHIVE_CW_LABEL_VAR)?
+                .unwrap_or_else(|| "Content warning".to_owned()),
+            cw_reveal: env_var_opt(ARCHIVE_CW_REVEAL_VAR)?
+                .unwrap_or_else(|| "(Click to expand)".to_owned()),
+            cw_thumbnails,
+            related_robots,
         })
     }
 }
@@ -638,6 +1194,65 @@ where
     }
 }
 
+/// Middleware adding security-related headers to every response.
+fn security_headers() -> middleware::DefaultHeaders {
+    // Inline styles and data URI images are allowed so that placeholders can be rendered without
+    // extra requests, but everything else must come from this site
+    const CONTENT_SECURITY_POLICY: &str = "\
+        default-src 'self'; \
+        img-src 'self' data:; \
+        style-src 'self' 'unsafe-inline'; \
+        object-src 'none'; \
+        base-uri 'self'; \
+        form-action 'self'; \
+        frame-ancestors 'self'";
+
+    middleware::DefaultHeaders::new()
+        .header(header::CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::X_FRAME_OPTIONS, "SAMEORIGIN")
+        .header(header::REFERRER_POLICY, "strict-origin-when-cross-origin")
+}
+
+/// Registers every route of the site under the base path.
+fn configure_routes(
+    cfg: &mut web::ServiceConfig,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    trust_proxy: bool,
+    admin_credentials: Option<AdminCredentials>
+) {
+    // The landing page is at the base path with a trailing slash, so redirect there from the base
+    // path without one. This must come before the scope, which would otherwise match the base path
+    // itself
+    if !base_path::get().is_empty() {
+        cfg.service(web::resource(base_path::get()).to(base_path_redirect));
+    }
+
+    cfg.service(web::scope(base_path::get())
+        .service(fs::Files::new("/static", "./static"))
+        .service(fs::Files::new("/robot_images", robots::IMAGES_DIR))
+        .service(robots_txt)
+        .service(bootstrap_ids)
+        .service(bootstrap_alt)
+        .service(landing_page)
+        .service(all_robots)
+        .service(all_robots_paged)
+        .service(robot_page)
+        .service(robot_short_link)
+        .service(daily_robot)
+        .service(about_page)
+        // Pages which query the database differently on every request are rate limited
+        .service(web::resource("/search")
+            .wrap(RateLimit::new(rate_limiter.clone(), trust_proxy))
+            .route(web::get().to(search_robots)))
+        .service(web::resource("/random")
+            .wrap(RateLimit::new(rate_limiter, trust_proxy))
+            .route(web::get().to(random_robot)))
+        .service(web::scope("/admin")
+            .wrap(RequireAdmin::new(admin_credentials))
+            .service(admin_set_alt)));
+}
+
 #[aw::main]
 async fn main() -> Result<(), ServerError> {
     #[cfg(feature = "dotenv")] {
@@ -646,51 +1261,685 @@ async fn main() -> Result<(), ServerError> {
 
     env_logger::init();
 
+    if let Some(base_path) = env_var_opt(ARCHIVE_BASE_PATH_VAR)? {
+        base_path::init(&base_path);
+    }
+
     let instance_meta = {
         let meta = InstanceMeta::new_env()?;
         web::Data::new(meta)
     };
 
-    let pool = {
+    let page_cache = {
+        let ttl_secs = match env_var_opt(PAGE_CACHE_TTL_VAR)? {
+            Some(secs) => secs.parse::<u64>()
+                .map_err(|_| ServerError::Config(format!("invalid page cache time to live {:?}", secs)))?,
+            None => DEFAULT_PAGE_CACHE_TTL_SECS,
+        };
+        web::Data::new(PageCache::new(Duration::from_secs(ttl_secs)))
+    };
+
+    // Only trust forwarding headers if explicitly told to, since otherwise any client could set
+    // them to whatever address they like
+    let trust_proxy = match env_var_opt(TRUST_PROXY_VAR)?.as_deref() {
+        None | Some("false") | Some("0") => false,
+        Some("true") | Some("1") => true,
+        Some(val) => return Err(ServerError::Config(format!(
+            "{} must be true or false, got {:?}", TRUST_PROXY_VAR, val
+        ))),
+    };
+
+    // Rate limiting is off unless a rate is given. Clients are told apart by their address, so
+    // behind a reverse proxy it also needs TRUST_PROXY, or every client would share one limit.
+    // Setting the rate to zero disables it too
+    let rate_limiter = {
+        let per_minute = match env_var_opt(RATE_LIMIT_PER_MINUTE_VAR)? {
+            Some(per_minute) => per_minute.parse::<u32>()
+                .map_err(|_| ServerError::Config(format!("invalid rate limit {:?}", per_minute)))?,
+            None => 0,
+        };
+
+        let burst = match env_var_opt(RATE_LIMIT_BURST_VAR)? {
+            Some(burst) => burst.parse::<u32>()
+                .ok()
+                .filter(|&burst| burst > 0)
+                .ok_or_else(|| ServerError::Config(format!("invalid rate limit burst {:?}", burst)))?,
+            None => DEFAULT_RATE_LIMIT_BURST,
+        };
+
+        if per_minute > 0 {
+            Some(Arc::new(RateLimiter::new(burst, per_minute)))
+        } else {
+            None
+        }
+    };
+
+    let admin_credentials = {
+        let username = env_var_opt(ADMIN_USERNAME_VAR)?;
+        let password = env_var_opt(ADMIN_PASSWORD_VAR)?;
+        username
+            .zip(password)
+            .map(|(username, password)| AdminCredentials { username, password })
+    };
+
+    let pools = {
         let db_url = env::var(DB_URL_VAR)?;
-        PgPool::connect(&db_url).await?
+        let primary = PgPool::connect(&db_url).await?;
+
+        let replica = match env_var_opt(DB_REPLICA_URL_VAR)? {
+            Some(replica_url) => Some(PgPool::connect(&replica_url).await?),
+            None => None,
+        };
+
+        Pools::new(primary, replica)
     };
 
     let app_factory = move || {
         App::new()
+            .wrap(security_headers())
+            .wrap(AssignRequestId::new(trust_proxy))
             .app_data(instance_meta.clone())
-            .app_data(CloneData::new(pool.clone()))
-            .service(fs::Files::new("/static", "./static"))
-            .service(fs::Files::new("/robot_images", "./generated/robot_images"))
-            .service(bootstrap_ids)
-            .service(bootstrap_alt)
-            .service(landing_page)
-            .service(all_robots)
-            .service(all_robots_paged)
-            .service(robot_page)
-            .service(search_robots)
-            .service(daily_robot)
-            .service(random_robot)
-            .service(about_page)
+            .app_data(page_cache.clone())
+            .app_data(CloneData::new(pools.clone()))
+            .configure(|cfg| configure_routes(cfg, rate_limiter.clone(), trust_proxy, admin_credentials.clone()))
             .default_service(web::route().to(not_found))
     };
 
-    let http_server = HttpServer::new(app_factory);
+    // TLS is only used if both a certificate and a key are given
+    let tls_paths = match (env_var_opt(TLS_CERT_VAR)?, env_var_opt(TLS_KEY_VAR)?) {
+        (Some(cert_path), Some(key_path)) => Some((cert_path, key_path)),
+        (None, None) => None,
+        _ => return Err(ServerError::Config(format!(
+            "{} and {} must either both be set or both be unset", TLS_CERT_VAR, TLS_KEY_VAR
+        ))),
+    };
 
-    let http_server = match env::var(BIND_ADDR_VAR) {
-        Ok(addrs) => {
-            let mut http_server = http_server;
-            for addr in addrs.split_whitespace() {
-                http_server = http_server.bind(addr)?;
-            }
-            http_server
-        },
-        Err(env::VarError::NotPresent) => http_server.bind(DEFAULT_BIND_ADDR)?,
+    #[cfg(feature = "tls")]
+    let tls_config = match tls_paths {
+        Some((cert_path, key_path)) => Some(tls::load_config(&cert_path, &key_path)?),
+        None => None,
+    };
+
+    #[cfg(not(feature = "tls"))]
+    if tls_paths.is_some() {
+        return Err(ServerError::Config(format!(
+            "{} and {} are set, but the server was built without the tls feature", TLS_CERT_VAR, TLS_KEY_VAR
+        )));
+    }
+
+    let bind_addrs = match env::var(BIND_ADDR_VAR) {
+        Ok(addrs) => addrs.split_whitespace().map(str::to_owned).collect::<Vec<_>>(),
+        Err(env::VarError::NotPresent) => vec![DEFAULT_BIND_ADDR.to_owned()],
         Err(err) => return Err(err.into()),
     };
 
+    let mut http_server = HttpServer::new(app_factory);
+
+    for addr in &bind_addrs {
+        #[cfg(feature = "tls")]
+        if let Some(tls_config) = &tls_config {
+            http_server = http_server.bind_rustls(addr, tls_config.clone())?;
+            continue;
+        }
+
+        http_server = http_server.bind(addr)?;
+    }
+
     http_server
         .run()
         .await
         .map_err(ServerError::from)
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test};
+    use chrono::TimeZone;
+
+    use db::tests::{connect, connect_empty, drop_schema, insert_robot};
+
+    use super::*;
+
+    fn meta() -> InstanceMeta {
+        InstanceMeta {
+            name: None,
+            url_prefix: None,
+            search_language: SearchLanguage::default(),
+            stop_words: StopWords::default(),
+            cw_label: "Content warning".to_owned(),
+            cw_reveal: "(Click to expand)".to_owned(),
+            cw_thumbnails: CwThumbnails::Blur,
+            related_robots: DEFAULT_RELATED_ROBOTS,
+        }
+    }
+
+    fn render_robot_page(meta: &InstanceMeta, robot: RobotFull) -> String {
+        render_robot(meta, robot, &[], None).markup.into_string()
+    }
+
+    async fn first_robot_id(pool: &PgPool) -> i32 {
+        sqlx::query_scalar("SELECT id FROM robots ORDER BY id LIMIT 1")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn custom_alt(pool: &PgPool, id: i32) -> Option<String> {
+        sqlx::query_scalar("SELECT custom_alt FROM robots WHERE id = $1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    /// Submits the alt text form for the given robot to the admin endpoint, logging in with the
+    /// given password.
+    async fn set_alt(pool: &PgPool, id: i32, alt: &str, password: &str) -> StatusCode {
+        let app = test::init_service(App::new()
+            .app_data(web::Data::new(PageCache::new(Duration::from_secs(60))))
+            .app_data(CloneData::new(Pools::new(pool.clone(), None)))
+            .service(web::scope("/admin")
+                .wrap(RequireAdmin::new(Some(AdminCredentials {
+                    username: "admin".to_owned(),
+                    password: "hunter2".to_owned(),
+                })))
+                .service(admin_set_alt)))
+            .await;
+
+        let credentials = base64::encode(format!("admin:{}", password));
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/admin/robots/{}/alt", id))
+            .insert_header((header::AUTHORIZATION, format!("Basic {}", credentials)))
+            .insert_header((header::CONTENT_TYPE, "application/x-www-form-urlencoded"))
+            .set_payload(format!("alt={}", alt))
+            .to_request();
+
+        test::call_service(&app, req).await.status()
+    }
+
+    #[actix_rt::test]
+    async fn security_headers_added_to_responses() {
+        let app = test::init_service(App::new()
+            .wrap(security_headers())
+            .route("/", web::get().to(HttpResponse::Ok)))
+            .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        let headers = res.headers();
+
+        let csp = headers.get(header::CONTENT_SECURITY_POLICY).unwrap().to_str().unwrap();
+        assert!(csp.contains("default-src 'self'"));
+        assert!(csp.contains("frame-ancestors 'self'"));
+        assert!(!csp.contains("script-src"), "scripts should fall back to default-src");
+
+        assert_eq!(headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "SAMEORIGIN");
+        assert_eq!(headers.get(header::REFERRER_POLICY).unwrap(), "strict-origin-when-cross-origin");
+    }
+
+    #[actix_rt::test]
+    async fn robots_txt_disallows_dynamic_and_private_pages() {
+        let app = test::init_service(App::new().service(robots_txt)).await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/robots.txt").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = test::read_body(res).await;
+        let lines = std::str::from_utf8(&body).unwrap().lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "User-agent: *");
+        assert!(lines.contains(&"Allow: /"));
+        assert!(lines.contains(&"Disallow: /random"));
+        assert!(lines.contains(&"Disallow: /search"));
+        assert!(lines.contains(&"Disallow: /admin/"));
+    }
+
+    #[actix_rt::test]
+    async fn robots_txt_includes_base_path() {
+        let _base_path = base_path::tests::set_for_thread("archive");
+        let app = test::init_service(App::new()
+            .configure(|cfg| configure_routes(cfg, None, false, None)))
+            .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/archive/robots.txt").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = test::read_body(res).await;
+        let lines = std::str::from_utf8(&body).unwrap().lines().collect::<Vec<_>>();
+
+        assert!(lines.contains(&"Allow: /archive/"));
+        assert!(lines.contains(&"Disallow: /archive/random"));
+        assert!(lines.contains(&"Disallow: /archive/search"));
+        assert!(lines.contains(&"Disallow: /archive/admin/"));
+    }
+
+    #[actix_rt::test]
+    async fn bare_base_path_redirects_to_landing_page() {
+        let _base_path = base_path::tests::set_for_thread("archive");
+        let app = test::init_service(App::new()
+            .configure(|cfg| configure_routes(cfg, None, false, None)))
+            .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/archive").to_request()).await;
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers().get(header::LOCATION).unwrap(), "/archive/");
+    }
+
+    #[test]
+    fn pagination_links_include_base_path() {
+        let _base_path = base_path::tests::set_for_thread("archive");
+        let markup = render_pagination(Pagination::try_new(5, 20).unwrap()).into_string();
+
+        for page in ["5", "1", "20", "7"] {
+            assert!(markup.contains(&format!("href=\"/archive/all/{}\"", page)), "no link to page {}", page);
+        }
+        assert!(!markup.contains("href=\"/all/"));
+    }
+
+    #[test]
+    fn robot_page_says_when_robot_was_posted() {
+        let mut robot = robots::tests::full(1, 42);
+        robot.tweet_time = Utc.ymd(2021, 3, 14).and_hms(15, 9, 26);
+
+        let page = render_robot_page(&meta(), robot);
+
+        assert!(page.contains("Posted on <time datetime=\"2021-03-14T15:09:26+00:00\">14 March 2021</time>"));
+    }
+
+    #[test]
+    fn robot_page_shows_short_link() {
+        let page = render_robot_page(&meta(), robots::tests::full(1, 42));
+        assert!(page.contains("Short link: <a class=\"link_text\" href=\"/r/42\">/r/42</a>"));
+
+        let meta = InstanceMeta {
+            url_prefix: Some("https://example.org".to_owned()),
+            ..meta()
+        };
+
+        let page = render_robot_page(&meta, robots::tests::full(1, 42));
+        assert!(page.contains("href=\"https://example.org/r/42\">https://example.org/r/42</a>"));
+    }
+
+    /// Returns the image description shown on the page of a robot with the given alt texts, along
+    /// with the robot's own alt text, rendered as it would appear in the page.
+    fn image_description(alt: Option<&str>, custom_alt: Option<&str>) -> (String, String) {
+        let _images = robots::tests::assume_images_exist();
+
+        let mut robot = robots::tests::full(1, 42);
+        robot.image_path = Some("tea.png".to_owned());
+        robot.alt = alt.map(str::to_owned);
+        robot.custom_alt = custom_alt.map(str::to_owned);
+
+        let image_alt = html! { (robot.image_alt()) }.into_string();
+        let page = render_robot_page(&meta(), robot);
+
+        let start = page.find("<summary>Image description</summary><p>")
+            .expect("page has no image description")
+            + "<summary>Image description</summary><p>".len();
+        let len = page[start..].find("</p>").unwrap();
+
+        (page[start..start + len].to_owned(), image_alt)
+    }
+
+    #[test]
+    fn image_description_uses_custom_alt() {
+        let (description, image_alt) = image_description(None, Some("A small robot with a kettle"));
+        assert_eq!(description, "A small robot with a kettle");
+        assert_eq!(description, image_alt);
+    }
+
+    #[test]
+    fn image_description_uses_original_alt() {
+        let (description, image_alt) = image_description(Some("A small robot holding a cup of tea"), None);
+        assert_eq!(description, "A small robot holding a cup of tea");
+        assert_eq!(description, image_alt);
+    }
+
+    #[test]
+    fn image_description_falls_back_to_missing_alt_message() {
+        let (description, image_alt) = image_description(None, None);
+        assert!(description.starts_with("Sorry, no alt text was found for this robot."));
+        assert_eq!(description, image_alt);
+    }
+
+    #[test]
+    fn image_description_left_out_without_image() {
+        let page = render_robot_page(&meta(), robots::tests::full(1, 42));
+        assert!(!page.contains("Image description"));
+    }
+
+    #[test]
+    fn no_robots_page_shows_message() {
+        let res = render_no_robots("Random robot", NO_ROBOTS_MESSAGE);
+        let page = res.markup.into_string();
+
+        assert_eq!(res.status, StatusCode::OK);
+        assert!(page.contains("<title>Random robot"));
+        assert!(page.contains("<h2>Random robot</h2>"));
+        assert!(page.contains(&format!("<p>{}</p>", NO_ROBOTS_MESSAGE)));
+    }
+
+    fn exclusion_ids(exclusions: &Exclusions) -> Vec<i32> {
+        exclusions.ids().iter().map(|id| id.0).collect()
+    }
+
+    #[test]
+    fn exclusions_parsed_from_list() {
+        assert_eq!(exclusion_ids(&Exclusions::from_list("3, 1,x,,-2, 7 ")), vec![3, 1, -2, 7]);
+        assert_eq!(exclusion_ids(&Exclusions::from_list("")), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn exclusions_keep_most_recent_ids() {
+        let list = (1..=20).map(|id| id.to_string()).collect::<Vec<_>>().join(",");
+        let exclusions = Exclusions::from_list(&list);
+
+        assert_eq!(exclusion_ids(&exclusions), (5..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn exclusions_forget_oldest_id_when_full() {
+        let mut exclusions = Exclusions::from_list("1,2,3");
+        exclusions.push(RobotId(4));
+        assert_eq!(exclusion_ids(&exclusions), vec![1, 2, 3, 4]);
+
+        let mut exclusions = Exclusions::from_list(&(1..=16).map(|id| id.to_string()).collect::<Vec<_>>().join(","));
+        exclusions.push(RobotId(17));
+        assert_eq!(exclusion_ids(&exclusions), (2..=17).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn exclusions_formatted_as_list() {
+        let mut exclusions = Exclusions::default();
+        assert_eq!(exclusions.to_string(), "");
+
+        exclusions.push(RobotId(3));
+        exclusions.push(RobotId(12));
+        assert_eq!(exclusions.to_string(), "3,12");
+        assert_eq!(exclusion_ids(&Exclusions::from_list(&exclusions.to_string())), vec![3, 12]);
+    }
+
+    #[test]
+    fn robot_found_by_slug() {
+        let mut coffee = robots::tests::preview(2, 7);
+        coffee.ident = "coffee".to_owned();
+        coffee.prefix = "Coffee".to_owned();
+        coffee.plural = Some("s".to_owned());
+
+        let robots = [robots::tests::preview(1, 7), coffee];
+
+        assert_eq!(find_by_slug(&robots, "teabot").unwrap().id, RobotId(1));
+        assert_eq!(find_by_slug(&robots, "coffeebots").unwrap().id, RobotId(2));
+        assert!(find_by_slug(&robots, "coffee").is_none());
+        assert!(find_by_slug(&robots, "Teabot").is_none());
+        assert!(find_by_slug(&robots[..0], "teabot").is_none());
+    }
+
+    fn custom_cw_meta(cw_thumbnails: CwThumbnails) -> InstanceMeta {
+        InstanceMeta {
+            cw_label: "Heads up".to_owned(),
+            cw_reveal: "Show anyway".to_owned(),
+            cw_thumbnails,
+            ..meta()
+        }
+    }
+
+    fn preview_with_cw() -> RobotPreview {
+        let mut robot = robots::tests::preview(1, 42);
+        robot.image_thumb_path = Some("tea.png".to_owned());
+        robot.content_warning = Some("caffeine".to_owned());
+        robot
+    }
+
+    #[test]
+    fn robot_page_uses_custom_cw_copy() {
+        let mut robot = robots::tests::full(1, 42);
+        robot.content_warning = Some("caffeine".to_owned());
+
+        let page = render_robot_page(&custom_cw_meta(CwThumbnails::Blur), robot);
+
+        assert!(page.contains("<summary>Show anyway Heads up: caffeine</summary>"));
+        assert!(page.contains("content=\"Heads up: caffeine\""));
+        assert!(!page.contains("Content warning"));
+        assert!(!page.contains("(Click to expand)"));
+    }
+
+    #[test]
+    fn robot_card_blurs_cw_thumbnail() {
+        let _images = robots::tests::assume_images_exist();
+
+        let card = robot_card(&custom_cw_meta(CwThumbnails::Blur), &preview_with_cw(), None).into_string();
+
+        assert!(card.contains("class=\"robot_image_cw\""));
+        assert!(card.contains("src=\"/robot_images/tea.png\""));
+        assert!(card.contains("<p class=\"robot_cw\">Heads up: caffeine</p>"));
+    }
+
+    #[test]
+    fn robot_card_hides_cw_thumbnail() {
+        let _images = robots::tests::assume_images_exist();
+
+        let card = robot_card(&custom_cw_meta(CwThumbnails::Hide), &preview_with_cw(), None).into_string();
+
+        assert!(!card.contains("src="));
+        assert!(!card.contains("robot_image_cw"));
+        assert!(card.contains("<img alt=\"Heads up: caffeine\">"));
+        assert!(card.contains("<p class=\"robot_cw\">Heads up: caffeine</p>"));
+    }
+
+    #[test]
+    fn robot_card_shows_thumbnail_without_cw() {
+        let _images = robots::tests::assume_images_exist();
+
+        let mut robot = preview_with_cw();
+        robot.content_warning = None;
+
+        for cw_thumbnails in [CwThumbnails::Blur, CwThumbnails::Hide] {
+            let card = robot_card(&custom_cw_meta(cw_thumbnails), &robot, None).into_string();
+
+            assert!(card.contains("src=\"/robot_images/tea.png\""));
+            assert!(!card.contains("robot_image_cw"));
+            assert!(!card.contains("Heads up"));
+        }
+    }
+
+    #[test]
+    fn search_order_defaults_to_relevance() {
+        let query = web::Query::<SearchQuery>::from_query("query=tea").unwrap();
+        assert_eq!(query.order, SearchOrder::Relevance);
+    }
+
+    #[test]
+    fn search_order_can_be_toggled() {
+        let query = web::Query::<SearchQuery>::from_query("query=tea&order=number").unwrap();
+        assert_eq!(query.order, SearchOrder::Number);
+
+        let query = web::Query::<SearchQuery>::from_query("query=tea&order=relevance").unwrap();
+        assert_eq!(query.order, SearchOrder::Relevance);
+
+        assert!(web::Query::<SearchQuery>::from_query("query=tea&order=Number").is_err());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn admin_set_alt_updates_alt_when_authorized() {
+        let pool = connect().await;
+        let id = first_robot_id(&pool).await;
+        let original = custom_alt(&pool, id).await;
+
+        let status = set_alt(&pool, id, "A+small+robot", "hunter2").await;
+        let updated = custom_alt(&pool, id).await;
+
+        sqlx::query("UPDATE robots SET custom_alt = $1 WHERE id = $2")
+            .bind(original)
+            .bind(id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(updated.as_deref(), Some("A small robot"));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn admin_set_alt_rejects_unauthorized() {
+        let pool = connect().await;
+        let id = first_robot_id(&pool).await;
+        let original = custom_alt(&pool, id).await;
+
+        let status = set_alt(&pool, id, "Not+allowed", "wrong").await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(custom_alt(&pool, id).await, original);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn short_link_redirects_to_first_robot_with_number() {
+        let pool = connect().await;
+
+        let (robot_number, ident): (i32, String) = sqlx::query_as(
+            "SELECT robot_number, ident FROM robots ORDER BY id LIMIT 1"
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let app = test::init_service(App::new()
+            .app_data(CloneData::new(Pools::new(pool, None)))
+            .service(robot_short_link))
+            .await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/r/{}", robot_number))
+            .to_request();
+
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            res.headers().get(header::LOCATION).unwrap(),
+            &format!("/robot/{}/{}", robot_number, ident)
+        );
+    }
+
+    /// Requests the given page from an archive with no robots in it, returning the status and body.
+    async fn get_from_empty_archive(schema: &'static str, uri: &str) -> (StatusCode, String) {
+        let pool = connect_empty(schema).await;
+
+        let app = test::init_service(App::new()
+            .app_data(web::Data::new(meta()))
+            .app_data(CloneData::new(Pools::new(pool, None)))
+            .service(landing_page)
+            .service(daily_robot)
+            .route("/random", web::get().to(random_robot)))
+            .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        let status = res.status();
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+
+        drop_schema(schema).await;
+
+        (status, body)
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn landing_page_with_no_robots() {
+        let (status, body) = get_from_empty_archive("test_empty_landing", "/").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(NO_ROBOTS_MESSAGE));
+        assert!(!body.contains("See all robots"));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn daily_robot_with_no_robots() {
+        let (status, body) = get_from_empty_archive("test_empty_daily", "/daily").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<h2>Robot of the day</h2>"));
+        assert!(body.contains("No robot of the day has been chosen yet."));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn random_robot_with_no_robots() {
+        let (status, body) = get_from_empty_archive("test_empty_random", "/random?exclude=1,2").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<h2>Random robot</h2>"));
+        assert!(body.contains(NO_ROBOTS_MESSAGE));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn random_robot_skips_excluded_robots() {
+        let pool = connect().await;
+
+        let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM robots ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+
+        let (&kept, excluded) = ids.split_last().expect("database has no robots");
+        let excluded = excluded.iter().copied().map(RobotId).collect::<Vec<_>>();
+
+        for _ in 0..10 {
+            let robot = fetch_random_robot(&pool, &excluded).await.unwrap().unwrap();
+            assert_eq!(robot.id, RobotId(kept));
+        }
+
+        let all = ids.iter().copied().map(RobotId).collect::<Vec<_>>();
+        assert!(fetch_random_robot(&pool, &all).await.unwrap().is_none());
+    }
+
+    /// Gets the page of Teabot from an archive which also has robots named like it, showing at
+    /// most the given number of related robots.
+    async fn get_teabot_page(schema: &'static str, related_robots: u32) -> String {
+        let pool = connect_empty(schema).await;
+
+        insert_robot(&pool, 1, "tea", "Tea", "bot", "Teabot makes you a cup of tea").await;
+        insert_robot(&pool, 2, "xylophone", "Xylophone", "bot", "Xylophonebot plays tunes").await;
+        insert_robot(&pool, 3, "teapot", "Teapot", "bot", "Teapotbot keeps the tea warm").await;
+        insert_robot(&pool, 4, "teacup", "Teacup", "bot", "Teacupbot holds the tea").await;
+
+        let app = test::init_service(App::new()
+            .app_data(web::Data::new(InstanceMeta { related_robots, ..meta() }))
+            .app_data(CloneData::new(Pools::new(pool, None)))
+            .service(robot_page))
+            .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/robot/1/tea").to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+
+        drop_schema(schema).await;
+
+        body
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn robot_page_shows_similar_robots() {
+        let page = get_teabot_page("test_related", 2).await;
+
+        let related = &page[page.find("You might also like").expect("page has no related robots")..];
+
+        assert!(related.contains("href=\"/robot/3/teapot\""));
+        assert!(related.contains("href=\"/robot/4/teacup\""));
+        assert!(!related.contains("href=\"/robot/2/xylophone\""));
+        assert!(!related.contains("href=\"/robot/1/tea\""));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn robot_page_related_section_can_be_disabled() {
+        let page = get_teabot_page("test_related_disabled", 0).await;
+        assert!(!page.contains("You might also like"));
+    }
+}
@@ -1,16 +1,19 @@
 use maud::{DOCTYPE, Markup, html};
 
+use crate::base_path;
+
 /// Returns a basic page whose body consists of three sections: header, main and footer,
-/// in that order.
-pub fn base_page(title: &str, header: Markup, main: Markup, footer: Markup) -> Markup {
+/// in that order. Any extra markup in `head` is appended to the page's `<head>`.
+pub fn base_page(title: &str, head: Markup, header: Markup, main: Markup, footer: Markup) -> Markup {
     html! {
         (DOCTYPE)
         html {
             head {
                 meta charset="utf-8";
                 meta name="viewport" content="width=device-width, initial-scale=1";
-                link rel="stylesheet" href="/static/style/main.css";
+                link rel="stylesheet" href=(base_path::link("/static/style/main.css"));
                 title { (title) }
+                (head)
             }
 
             body {
@@ -32,7 +35,13 @@ pub fn base_page(title: &str, header: Markup, main: Markup, footer: Markup) -> M
 
 /// Returns a page with the default header and footer.
 pub fn archive_page(title: &str, content: Markup) -> Markup {
-    base_page(title, header(), html! { div class="content" { (content) } }, footer())
+    archive_page_with_head(title, html! {}, content)
+}
+
+/// Returns a page with the default header and footer, and some extra markup such as metadata
+/// tags in the page's `<head>`.
+pub fn archive_page_with_head(title: &str, head: Markup, content: Markup) -> Markup {
+    base_page(title, head, header(), html! { div class="content" { (content) } }, footer())
 }
 
 /// The default header, containing a navigation menu and search bar.
@@ -48,15 +57,15 @@ pub fn header() -> Markup {
         div class="nav_container content" {
             nav class="site_nav" {
                 ul {
-                    li { a class="link_text" href="/" { "Home" } }
-                    li { a class="link_text" href="/all" { "All robots" } }
-                    li { a class="link_text" href="/daily" { "Robot of the day" } }
-                    li { a class="link_text" href="/random" { "Random" } }
-                    li { a class="link_text" href="/about" { "About" } }
+                    li { a class="link_text" href=(base_path::link("/")) { "Home" } }
+                    li { a class="link_text" href=(base_path::link("/all")) { "All robots" } }
+                    li { a class="link_text" href=(base_path::link("/daily")) { "Robot of the day" } }
+                    li { a class="link_text" href=(base_path::link("/random")) { "Random" } }
+                    li { a class="link_text" href=(base_path::link("/about")) { "About" } }
                 }
 
                 //TODO: submit button
-                form class="search_bar_container" method="get" action="/search" {
+                form class="search_bar_container" method="get" action=(base_path::link("/search")) {
                     input
                         class="search_bar"
                         name="query"
@@ -82,3 +91,29 @@ pub fn footer() -> Markup {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nav_links_include_base_path() {
+        let _base_path = base_path::tests::set_for_thread("archive");
+
+        let page = archive_page("Test", html! {}).into_string();
+
+        for path in &["/", "/all", "/daily", "/random", "/about"] {
+            assert!(page.contains(&format!("href=\"/archive{}\"", path)), "no link to {}", path);
+        }
+        assert!(page.contains("action=\"/archive/search\""));
+        assert!(page.contains("href=\"/archive/static/style/main.css\""));
+    }
+
+    #[test]
+    fn nav_links_without_base_path() {
+        let page = archive_page("Test", html! {}).into_string();
+
+        assert!(page.contains("href=\"/all\""));
+        assert!(page.contains("action=\"/search\""));
+    }
+}
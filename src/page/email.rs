@@ -0,0 +1,129 @@
+use maud::{DOCTYPE, html};
+
+use crate::robots::{Displayable, Linkable, Named, RobotFull};
+
+const FONT_STACK: &str = "Gill Sans, Gill Sans MT, Calibri, sans-serif";
+const ACCENT_COLOUR: &str = "#7A3E76";
+
+/// Returns a self-contained HTML email announcing the robot of the day. Email clients ignore
+/// stylesheets and have no page to resolve relative links against, so every style is inline and
+/// every URL is made absolute using `url_prefix`, the URL the site is served from.
+///
+/// The image and body of a robot with a content warning are left out, since an email has no way
+/// of hiding them behind the warning like the robot's page does.
+// Nothing sends this yet; it is for the planned mailing list
+#[allow(dead_code)]
+pub fn daily_email(robot: &RobotFull, url_prefix: &str, cw_label: &str) -> String {
+    let url_prefix = url_prefix.trim_end_matches('/');
+
+    let full_name = robot.full_name();
+    let page_url = format!("{}{}", url_prefix, robot.page_link());
+
+    let image_url = robot.content_warning
+        .is_none()
+        .then(|| robot.image_resource_url_absolute(url_prefix))
+        .flatten();
+
+    html! {
+        (DOCTYPE)
+        html {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title { "Robot of the day: " (full_name) }
+            }
+
+            body style=(format!("margin: 0; padding: 24px; background-color: #FFFFFF; color: #202020; font-family: {};", FONT_STACK)) {
+                div style="max-width: 600px; margin: 0 auto;" {
+                    p style=(format!("margin: 0 0 8px; color: {};", ACCENT_COLOUR)) {
+                        "Robot of the day"
+                    }
+
+                    h1 style="margin: 0 0 16px; font-size: 24px;" {
+                        "#" (robot.robot_number) " " (full_name)
+                    }
+
+                    @match robot.content_warning.as_deref() {
+                        Some(content_warning) => {
+                            p style="margin: 0 0 16px; font-weight: bold;" {
+                                (cw_label) ": " (content_warning)
+                            }
+                        }
+
+                        None => {
+                            @if let Some(image_url) = &image_url {
+                                a href=(page_url) {
+                                    img
+                                        src=(image_url)
+                                        alt=(robot.image_alt())
+                                        width="600"
+                                        style="display: block; width: 100%; max-width: 600px; height: auto; border: 0;";
+                                }
+                            }
+
+                            p style="margin: 16px 0; font-size: 16px; line-height: 1.5;" {
+                                (robot.body)
+                            }
+                        }
+                    }
+
+                    p style="margin: 16px 0 0;" {
+                        a href=(page_url) style=(format!("color: {};", ACCENT_COLOUR)) {
+                            "See this robot on the Small Robots Archive"
+                        }
+                    }
+                }
+            }
+        }
+    }
+    .into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::robots::{tests::assume_images_exist, RobotId, RobotNumber};
+
+    use super::*;
+
+    fn robot(image_path: Option<&str>, content_warning: Option<&str>) -> RobotFull {
+        RobotFull {
+            id: RobotId(1),
+            robot_number: RobotNumber(42),
+            ident: "tea".to_owned(),
+            prefix: "Tea".to_owned(),
+            suffix: "bot".to_owned(),
+            plural: None,
+            content_warning: content_warning.map(str::to_owned),
+            image_path: image_path.map(str::to_owned),
+            alt: Some("A small robot holding a cup of tea".to_owned()),
+            custom_alt: None,
+            body: "Teabot makes you a cup of tea when you need one".to_owned(),
+            tweet_id: 1,
+            tweet_time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn email_has_absolute_image_url_and_full_name() {
+        let _images = assume_images_exist();
+
+        let email = daily_email(&robot(Some("tea.png"), None), "https://example.org/", "CW");
+
+        assert!(email.contains("src=\"https://example.org/robot_images/tea.png\""));
+        assert!(email.contains("href=\"https://example.org/robot/42/tea\""));
+        assert!(email.contains("Teabot"));
+        assert!(email.contains("A small robot holding a cup of tea"));
+        assert!(!email.contains("<link"));
+    }
+
+    #[test]
+    fn email_leaves_out_content_behind_warning() {
+        let email = daily_email(&robot(None, Some("caffeine")), "https://example.org", "CW");
+
+        assert!(email.contains("CW: caffeine"));
+        assert!(email.contains("Teabot"));
+        assert!(!email.contains("cup of tea"));
+    }
+}
@@ -28,7 +28,10 @@ pub fn error_page(status: StatusCode) -> Markup {
 fn error_message(status: StatusCode) -> Option<&'static str> {
     match status {
         StatusCode::BAD_REQUEST => Some("We don't understand that request"),
+        StatusCode::UNAUTHORIZED => Some("You need to log in to see this page"),
+        StatusCode::FORBIDDEN => Some("You aren't allowed to do that"),
         StatusCode::NOT_FOUND => Some("We couldn't find that page"),
+        StatusCode::TOO_MANY_REQUESTS => Some("You're going a bit too fast! Please wait a moment and try again"),
         StatusCode::INTERNAL_SERVER_ERROR => Some("Something went wrong on our end"),
         _ => None,
     }
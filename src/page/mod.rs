@@ -1,5 +1,11 @@
 mod base;
+mod email;
 mod error;
+mod search;
 
 pub use base::*;
+// Nothing sends the daily email until the mailing list exists
+#[allow(unused_imports)]
+pub use email::*;
 pub use error::*;
+pub use search::*;
@@ -0,0 +1,33 @@
+use maud::{html, Markup};
+
+/// The heading shown above search results, saying how many robots were found for the query.
+pub fn search_summary(query: &str, count: usize) -> Markup {
+    let found = match count {
+        0 => "No robots".to_owned(),
+        1 => "1 robot".to_owned(),
+        n => format!("{} robots", n),
+    };
+
+    html! {
+        h2 class="word_break" { (found) " found for \"" (query) "\"" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_escapes_query() {
+        let summary = search_summary("<script>salt & pepper", 2).into_string();
+        assert!(summary.contains("&lt;script&gt;salt &amp; pepper"));
+        assert!(!summary.contains("<script>"));
+    }
+
+    #[test]
+    fn summary_counts_robots() {
+        assert!(search_summary("tea", 0).into_string().contains("No robots found for"));
+        assert!(search_summary("tea", 1).into_string().contains("1 robot found for"));
+        assert!(search_summary("tea", 48).into_string().contains("48 robots found for"));
+    }
+}
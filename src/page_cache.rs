@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use maud::Markup;
+
+/// The most pages the cache will hold at once, so that requests for many different pages cannot
+/// use up an unbounded amount of memory.
+const MAX_ENTRIES: usize = 256;
+
+/// An in-memory cache of rendered pages, for pages which are expensive to render but change
+/// rarely. Entries expire after a fixed time to live, so changes made to the database by the
+/// importer show up without restarting the server.
+#[derive(Debug)]
+pub struct PageCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Markup)>>,
+}
+
+impl PageCache {
+    /// Creates a cache whose entries expire after the given time. A time to live of zero disables
+    /// the cache.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached page with the given key, if there is one which has not expired.
+    pub fn get(&self, key: &str) -> Option<Markup> {
+        let entries = self.entries.lock().ok()?;
+
+        entries
+            .get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, markup)| markup.clone())
+    }
+
+    /// Caches the page with the given key. If the cache is full, expired entries are removed to
+    /// make room, and the page is not cached if that does not free any space.
+    pub fn insert(&self, key: String, markup: Markup) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let ttl = self.ttl;
+            entries.retain(|_, (inserted, _)| inserted.elapsed() < ttl);
+
+            if entries.len() >= MAX_ENTRIES {
+                return;
+            }
+        }
+
+        entries.insert(key, (Instant::now(), markup));
+    }
+
+    /// Removes every page from the cache, for when the data shown on the cached pages changes.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use maud::html;
+
+    use super::*;
+
+    fn page(text: &str) -> Markup {
+        html! { p { (text) } }
+    }
+
+    #[test]
+    fn returns_cached_pages() {
+        let cache = PageCache::new(Duration::from_secs(60));
+        cache.insert("/robots".to_owned(), page("all robots"));
+
+        assert_eq!(cache.get("/robots").map(Markup::into_string), Some(page("all robots").into_string()));
+        assert!(cache.get("/robots/2").is_none());
+    }
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let cache = PageCache::new(Duration::from_millis(20));
+        cache.insert("/robots".to_owned(), page("all robots"));
+        assert!(cache.get("/robots").is_some());
+
+        thread::sleep(Duration::from_millis(40));
+        assert!(cache.get("/robots").is_none());
+    }
+
+    #[test]
+    fn zero_ttl_disables_cache() {
+        let cache = PageCache::new(Duration::from_secs(0));
+        cache.insert("/robots".to_owned(), page("all robots"));
+        assert!(cache.get("/robots").is_none());
+    }
+
+    #[test]
+    fn max_entries_respected() {
+        let cache = PageCache::new(Duration::from_secs(60));
+
+        for i in 0..(MAX_ENTRIES + 10) {
+            cache.insert(format!("/robots/{}", i), page("robots"));
+        }
+
+        assert_eq!(cache.entries.lock().unwrap().len(), MAX_ENTRIES);
+        assert!(cache.get("/robots/0").is_some());
+        assert!(cache.get(&format!("/robots/{}", MAX_ENTRIES)).is_none());
+
+        // Pages which are already cached can still be replaced when the cache is full
+        cache.insert("/robots/0".to_owned(), page("new robots"));
+        assert_eq!(cache.get("/robots/0").map(Markup::into_string), Some(page("new robots").into_string()));
+    }
+
+    #[test]
+    fn full_cache_makes_room_by_removing_expired_entries() {
+        let cache = PageCache::new(Duration::from_millis(20));
+
+        for i in 0..MAX_ENTRIES {
+            cache.insert(format!("/robots/{}", i), page("robots"));
+        }
+
+        thread::sleep(Duration::from_millis(40));
+        cache.insert("/about".to_owned(), page("about"));
+
+        assert!(cache.get("/about").is_some());
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn clear_empties_cache() {
+        let cache = PageCache::new(Duration::from_secs(60));
+        cache.insert("/robots".to_owned(), page("all robots"));
+        cache.insert("/robots/2".to_owned(), page("more robots"));
+
+        cache.clear();
+
+        assert!(cache.get("/robots").is_none());
+        assert!(cache.get("/robots/2").is_none());
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::future::{self, Future};
+use std::net::{IpAddr, Ipv6Addr};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    self as aw,
+    dev::{forward_ready, AnyBody, Service, ServiceRequest, ServiceResponse, Transform},
+};
+
+use crate::client_ip;
+use crate::error::SiteError;
+
+/// The most clients the limiter keeps track of at once. When there are more, clients whose
+/// buckets have refilled are forgotten, since they are no different from new clients. If that is
+/// not enough, the clients seen least recently are forgotten until there are at most
+/// `PRUNED_TRACKED_CLIENTS`, so that the cost of pruning is spread across many new clients.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+const PRUNED_TRACKED_CLIENTS: usize = MAX_TRACKED_CLIENTS / 10 * 9;
+
+/// A token bucket rate limiter, keyed by client IP address. Each client has a bucket holding up
+/// to `burst` tokens which refills at a steady rate; each request takes a token, and requests
+/// are rejected when the bucket is empty. IPv6 clients are keyed by their /64 network, since a
+/// single client is usually given a whole /64 to pick addresses from.
+#[derive(Debug)]
+pub struct RateLimiter {
+    burst: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing bursts of up to `burst` requests, and `per_minute` requests per
+    /// minute after that.
+    pub fn new(burst: u32, per_minute: u32) -> Self {
+        Self {
+            burst: f64::from(burst),
+            per_second: f64::from(per_minute) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the client's bucket. If the bucket is empty, returns how long the client
+    /// must wait before a token is available.
+    fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let key = client_key(ip);
+
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            // Failing open is preferable to rejecting every request
+            Err(_) => return Ok(()),
+        };
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&key) {
+            self.prune(&mut buckets, now);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        bucket.tokens = bucket.refilled(now, self.burst, self.per_second);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second))
+        }
+    }
+
+    /// Forgets clients until there are at most `PRUNED_TRACKED_CLIENTS`, starting with the ones
+    /// whose buckets have refilled and then the ones seen least recently.
+    fn prune(&self, buckets: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        let (burst, per_second) = (self.burst, self.per_second);
+        buckets.retain(|_, bucket| bucket.refilled(now, burst, per_second) < burst);
+
+        if buckets.len() > PRUNED_TRACKED_CLIENTS {
+            let excess = buckets.len() - PRUNED_TRACKED_CLIENTS;
+            let mut updated = buckets.values().map(|bucket| bucket.updated).collect::<Vec<_>>();
+            let (_, &mut cutoff, _) = updated.select_nth_unstable(excess - 1);
+            buckets.retain(|_, bucket| bucket.updated > cutoff);
+        }
+    }
+}
+
+/// The key the client's bucket is stored under: IPv4 addresses are used as they are, and IPv6
+/// addresses are truncated to their /64 network.
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6 addresses
+            Some(ipv4) if ip.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => IpAddr::V4(ipv4),
+            _ => {
+                let segments = ip.segments();
+                IpAddr::V6(Ipv6Addr::new(segments[0], segments[1], segments[2], segments[3], 0, 0, 0, 0))
+            },
+        },
+    }
+}
+
+impl Bucket {
+    /// The number of tokens in the bucket at the given time.
+    fn refilled(&self, now: Instant, burst: f64, per_second: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * per_second).min(burst)
+    }
+}
+
+/// Middleware which rejects requests with a 429 response once their client has exceeded the
+/// rate limit. The limiter is shared, so the same limit applies across every worker thread. If
+/// there is no limiter, every request is allowed.
+pub struct RateLimit {
+    limiter: Rc<Option<Arc<RateLimiter>>>,
+    trust_proxy: bool,
+}
+
+impl RateLimit {
+    /// `trust_proxy` determines how the client address is found; see [client_ip::client_ip].
+    pub fn new(limiter: Option<Arc<RateLimiter>>, trust_proxy: bool) -> Self {
+        Self {
+            limiter: Rc::new(limiter),
+            trust_proxy,
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<AnyBody>, Error = aw::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<AnyBody>;
+    type Error = aw::Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ready(Ok(RateLimitMiddleware {
+            service,
+            limiter: self.limiter.clone(),
+            trust_proxy: self.trust_proxy,
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Rc<Option<Arc<RateLimiter>>>,
+    trust_proxy: bool,
+}
+
+impl<S> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<AnyBody>, Error = aw::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<AnyBody>;
+    type Error = aw::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let limiter = match self.limiter.as_ref() {
+            Some(limiter) => limiter,
+            None => return Box::pin(self.service.call(req)),
+        };
+
+        // Requests whose address cannot be determined are let through, since there is no way to
+        // tell them apart
+        let limited = client_ip::client_ip(&req, self.trust_proxy)
+            .and_then(|ip| limiter.check(ip).err().map(|retry_after| (ip, retry_after)));
+
+        match limited {
+            None => Box::pin(self.service.call(req)),
+            Some((ip, retry_after)) => {
+                // Round up, so that clients which wait the full time are never rejected again
+                let retry_after_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+                let err = SiteError::TooManyRequests { retry_after_secs }
+                    .report(format!("rate limit exceeded by {} for {}", ip, req.path()));
+                Box::pin(future::ready(Ok(req.error_response(err))))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use actix_web::{http::{header, StatusCode}, test, web, App, HttpResponse};
+
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn rejects_client_after_burst() {
+        let limiter = RateLimiter::new(3, 1);
+
+        for _ in 0..3 {
+            assert!(limiter.check(ip("192.0.2.1")).is_ok());
+        }
+
+        let retry_after = limiter.check(ip("192.0.2.1")).unwrap_err();
+        assert!(retry_after > Duration::from_secs(50) && retry_after <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn other_clients_are_unaffected() {
+        let limiter = RateLimiter::new(1, 1);
+
+        assert!(limiter.check(ip("192.0.2.1")).is_ok());
+        assert!(limiter.check(ip("192.0.2.1")).is_err());
+        assert!(limiter.check(ip("192.0.2.2")).is_ok());
+    }
+
+    #[test]
+    fn ipv6_clients_share_their_network_limit() {
+        let limiter = RateLimiter::new(1, 1);
+
+        assert!(limiter.check(ip("2001:db8:1:2::1")).is_ok());
+        assert!(limiter.check(ip("2001:db8:1:2:ffff::2")).is_err());
+        assert!(limiter.check(ip("2001:db8:1:3::1")).is_ok());
+    }
+
+    #[test]
+    fn client_key_unwraps_ipv4_mapped_addresses() {
+        assert_eq!(client_key(ip("::ffff:192.0.2.1")), ip("192.0.2.1"));
+        assert_eq!(client_key(ip("192.0.2.1")), ip("192.0.2.1"));
+        assert_eq!(client_key(ip("2001:db8:1:2:3:4:5:6")), ip("2001:db8:1:2::"));
+    }
+
+    #[test]
+    fn tracked_clients_are_capped() {
+        // None of these buckets refill in time to be forgotten, so the least recently seen clients
+        // must be dropped instead
+        let limiter = RateLimiter::new(5, 1);
+
+        for i in 0..(MAX_TRACKED_CLIENTS as u32 + 100) {
+            assert!(limiter.check(IpAddr::V4(Ipv4Addr::from(i))).is_ok());
+        }
+
+        let tracked = limiter.buckets.lock().unwrap().len();
+        assert!(tracked <= MAX_TRACKED_CLIENTS);
+        assert!(tracked > 0);
+    }
+
+    #[actix_rt::test]
+    async fn middleware_responds_too_many_requests() {
+        let limiter = Arc::new(RateLimiter::new(1, 1));
+        let app = test::init_service(App::new()
+            .wrap(RateLimit::new(Some(limiter), false))
+            .route("/", web::get().to(HttpResponse::Ok)))
+            .await;
+
+        let request_from = |addr: &str| test::TestRequest::get()
+            .peer_addr(addr.parse::<SocketAddr>().unwrap())
+            .to_request();
+
+        assert_eq!(test::call_service(&app, request_from("192.0.2.1:1234")).await.status(), StatusCode::OK);
+
+        let res = test::call_service(&app, request_from("192.0.2.1:1234")).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(res.headers().contains_key(header::RETRY_AFTER));
+
+        assert_eq!(test::call_service(&app, request_from("192.0.2.2:1234")).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,209 @@
+use std::future::{self, Future};
+use std::net::IpAddr;
+use std::pin::Pin;
+
+use actix_web::{
+    self as aw,
+    dev::{forward_ready, AnyBody, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    HttpMessage,
+};
+use uuid::Uuid;
+
+use crate::client_ip;
+use crate::error::SiteReportError;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest request id accepted from a client. Longer ids are replaced with a generated one.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// An id identifying a single request, so that log lines can be tied to the response a user saw.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Uses the id from the request's `X-Request-Id` header if it has a valid one, so that ids
+    /// assigned by a reverse proxy are kept, and generates a new one otherwise.
+    fn from_request(req: &ServiceRequest) -> Self {
+        let provided = req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|val| val.to_str().ok())
+            .filter(|id| is_valid_id(id));
+
+        match provided {
+            Some(id) => RequestId(id.to_owned()),
+            None => RequestId(Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+/// Returns true if the id is safe to write to the logs and echo back in a header.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+fn log_error(err: &aw::Error, request_id: &RequestId, client_ip: Option<IpAddr>) {
+    if let Some(report) = err.as_error::<SiteReportError>() {
+        report.log(&request_id.0, client_ip);
+    }
+}
+
+/// Middleware which assigns every request an id and returns it in the `X-Request-Id` response
+/// header. It is also responsible for logging errors which occur while handling the request, so
+/// that each error is logged alongside the id of the request which caused it.
+pub struct AssignRequestId {
+    trust_proxy: bool,
+}
+
+impl AssignRequestId {
+    /// `trust_proxy` determines how the client address in the logs is found; see
+    /// [client_ip::client_ip].
+    pub fn new(trust_proxy: bool) -> Self {
+        Self {
+            trust_proxy,
+        }
+    }
+}
+
+impl<S> Transform<S, ServiceRequest> for AssignRequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<AnyBody>, Error = aw::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<AnyBody>;
+    type Error = aw::Error;
+    type Transform = AssignRequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ready(Ok(AssignRequestIdMiddleware {
+            service,
+            trust_proxy: self.trust_proxy,
+        }))
+    }
+}
+
+pub struct AssignRequestIdMiddleware<S> {
+    service: S,
+    trust_proxy: bool,
+}
+
+impl<S> Service<ServiceRequest> for AssignRequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<AnyBody>, Error = aw::Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<AnyBody>;
+    type Error = aw::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let request_id = RequestId::from_request(&req);
+        req.extensions_mut().insert(request_id.clone());
+
+        let client_ip = client_ip::client_ip(&req, self.trust_proxy);
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = match fut.await {
+                Ok(res) => res,
+                // Without the request, the error cannot be turned into a response with the header,
+                // so inner middleware should return errors as responses instead
+                Err(err) => {
+                    log_error(&err, &request_id, client_ip);
+                    return Err(err);
+                },
+            };
+
+            if let Some(err) = res.response().error() {
+                log_error(err, &request_id, client_ip);
+            }
+
+            // The id has already been validated or generated, so it is always a valid header value
+            if let Ok(val) = HeaderValue::from_str(&request_id.0) {
+                res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), val);
+            }
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, web, App, HttpResponse};
+
+    use super::*;
+
+    async fn response_id(provided: Option<&str>) -> String {
+        let app = test::init_service(App::new()
+            .wrap(AssignRequestId::new(false))
+            .route("/", web::get().to(HttpResponse::Ok)))
+            .await;
+
+        let mut req = test::TestRequest::get();
+        if let Some(provided) = provided {
+            req = req.insert_header((REQUEST_ID_HEADER, provided));
+        }
+
+        let res = test::call_service(&app, req.to_request()).await;
+
+        res.headers()
+            .get(REQUEST_ID_HEADER)
+            .expect("response should have a request id")
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[actix_rt::test]
+    async fn generates_id() {
+        let id = response_id(None).await;
+        assert!(Uuid::parse_str(&id).is_ok());
+        assert_ne!(id, response_id(None).await);
+    }
+
+    #[actix_rt::test]
+    async fn echoes_provided_id() {
+        assert_eq!(response_id(Some("proxy-1234.abcd:5678_x")).await, "proxy-1234.abcd:5678_x");
+    }
+
+    #[actix_rt::test]
+    async fn replaces_invalid_id() {
+        for invalid in &["", "has spaces", "quote\"", &"x".repeat(MAX_REQUEST_ID_LEN + 1)] {
+            let id = response_id(Some(invalid)).await;
+            assert!(Uuid::parse_str(&id).is_ok(), "{:?} was not replaced", invalid);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn error_responses_have_id() {
+        let app = test::init_service(App::new()
+            .wrap(AssignRequestId::new(false))
+            .route("/", web::get().to(|| async {
+                Err::<HttpResponse, _>(crate::error::SiteError::NotFound.report("no robots here"))
+            })))
+            .await;
+
+        let req = test::TestRequest::get().insert_header((REQUEST_ID_HEADER, "abc")).to_request();
+        let res = test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), aw::http::StatusCode::NOT_FOUND);
+        assert_eq!(res.headers().get(REQUEST_ID_HEADER).unwrap(), "abc");
+    }
+
+    #[test]
+    fn valid_ids() {
+        assert!(is_valid_id("a"));
+        assert!(is_valid_id(&"x".repeat(MAX_REQUEST_ID_LEN)));
+        assert!(!is_valid_id("new\nline"));
+        assert!(!is_valid_id("ünïcode"));
+    }
+}
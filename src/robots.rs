@@ -1,14 +1,117 @@
+#[cfg(test)]
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
 use sqlx::FromRow;
+use unidecode::unidecode;
+
+use crate::base_path;
+
+/// The directory robot images are served from.
+pub(crate) const IMAGES_DIR: &str = "./generated/robot_images";
+
+/// How long to remember whether an image file exists. Pages show many robots, so checking the
+/// disk for every image on every request would block the worker thread for each one, but images
+/// added by the importer should still show up without restarting the server.
+const IMAGE_EXISTS_TTL: Duration = Duration::from_secs(60);
+
+/// Whether each image file existed when it was last checked, and when that was. The paths come
+/// from the database rather than from requests, so there are at most as many as there are robots.
+static IMAGE_EXISTS: Lazy<Mutex<HashMap<PathBuf, (bool, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns whether the robot image with the given file name exists on disk.
+fn image_exists(file_name: &str) -> bool {
+    #[cfg(test)]
+    if let Some(exists) = tests::IMAGES_EXIST.with(Cell::get) {
+        return exists;
+    }
+
+    cached_is_file(Path::new(IMAGES_DIR).join(file_name))
+}
+
+/// Returns the site-relative URL the robot image with the given file name is served from.
+fn image_url(file_name: &str) -> String {
+    const PREFIX: &str = "/robot_images/";
+
+    let base_path = base_path::get();
+
+    let mut buffer = String::with_capacity(base_path.len() + PREFIX.len() + file_name.len());
+    buffer.push_str(base_path);
+    buffer.push_str(PREFIX);
+    buffer.push_str(file_name);
+    buffer
+}
+
+/// Returns whether the file exists, using the cached answer if it was checked within the last
+/// `IMAGE_EXISTS_TTL`.
+fn cached_is_file(path: PathBuf) -> bool {
+    let now = Instant::now();
+
+    if let Ok(cache) = IMAGE_EXISTS.lock() {
+        if let Some(&(exists, checked)) = cache.get(&path) {
+            if now.saturating_duration_since(checked) < IMAGE_EXISTS_TTL {
+                return exists;
+            }
+        }
+    }
+
+    // The lock is not held while checking the disk, so other threads are not kept waiting
+    let exists = path.is_file();
+
+    if let Ok(mut cache) = IMAGE_EXISTS.lock() {
+        cache.insert(path, (exists, now));
+    }
+
+    exists
+}
+
+/// The number a robot was given when it was posted. Several robots may share the same number,
+/// so this does not identify a robot on its own.
+#[derive(sqlx::Type, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[sqlx(transparent)]
+#[serde(transparent)]
+pub(crate) struct RobotNumber(pub(crate) i32);
+
+impl fmt::Display for RobotNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The primary key of a robot in the robots table. Kept distinct from [RobotNumber] so that one
+/// cannot accidentally be bound in place of the other.
+#[derive(sqlx::Type, Deserialize, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[sqlx(transparent)]
+#[serde(transparent)]
+pub(crate) struct RobotId(pub(crate) i32);
+
+impl fmt::Display for RobotId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct RobotKey<'a> {
-    pub(crate) robot_number: i32,
+    pub(crate) robot_number: RobotNumber,
     pub(crate) ident: &'a str,
 }
 
 impl<'a> RobotKey<'a> {
     pub(crate) fn page_link(&self) -> String {
-        format!("/robot/{}/{}", self.robot_number, self.ident)
+        format!("{}/robot/{}/{}", base_path::get(), self.robot_number, self.ident)
+    }
+
+    pub(crate) fn short_link(&self) -> String {
+        format!("{}/r/{}", base_path::get(), self.robot_number)
     }
 }
 
@@ -35,6 +138,34 @@ impl<'a> RobotName<'a> {
 
         buffer
     }
+
+    /// Returns the byte index in [RobotName::full_name] at which the suffix starts, such as 3 for
+    /// "Teabot" or 4 for "Saltbots".
+    pub(crate) fn suffix_offset_in_full_name(self) -> usize {
+        self.prefix.len()
+    }
+
+    /// Returns a human-readable URL slug for the name, such as "salt-and-pepperbots". The name is
+    /// converted to lowercase ASCII, and each run of non-alphanumeric characters becomes a hyphen.
+    pub(crate) fn slug(self) -> String {
+        let full_name = unidecode(&self.full_name());
+
+        let mut slug = String::with_capacity(full_name.len());
+
+        for c in full_name.chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+
+        if slug.ends_with('-') {
+            slug.pop();
+        }
+
+        slug
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -45,16 +176,19 @@ pub(crate) struct RobotImage<'a> {
 }
 
 impl<'a> RobotImage<'a> {
+    /// Returns the URL of the image, or `None` if the robot has no image or its image file is
+    /// missing from disk, so that a placeholder is rendered instead of a broken image.
     fn resource_url(self) -> Option<String> {
-        const PREFIX: &str = "/robot_images/";
-
         self.file_name
-            .map(|file_name| {
-                let mut buffer = String::with_capacity(PREFIX.len() + file_name.len());
-                buffer.push_str(PREFIX);
-                buffer.push_str(file_name);
-                buffer
-            })
+            .filter(|file_name| image_exists(file_name))
+            .map(image_url)
+    }
+
+    /// Returns the absolute URL of the image, given the base URL the site is served from. The base
+    /// URL may or may not end with a slash.
+    fn resource_url_absolute(self, base: &str) -> Option<String> {
+        self.resource_url()
+            .map(|resource_url| format!("{}{}", base.trim_end_matches('/'), resource_url))
     }
 
     fn alt(self) -> &'a str {
@@ -74,6 +208,10 @@ pub(crate) trait Linkable {
     fn page_link(&self) -> String {
         self.key().page_link()
     }
+
+    fn short_link(&self) -> String {
+        self.key().short_link()
+    }
 }
 
 pub(crate) trait Named {
@@ -82,6 +220,19 @@ pub(crate) trait Named {
     fn full_name(&self) -> String {
         self.name().full_name()
     }
+
+    fn slug(&self) -> String {
+        self.name().slug()
+    }
+
+    /// Returns the full name split into the prefix and the rest of the name, which is the suffix
+    /// followed by the plural ending if there is one.
+    fn full_name_parts(&self) -> (String, String) {
+        let name = self.name();
+        let mut full_name = name.full_name();
+        let rest = full_name.split_off(name.suffix_offset_in_full_name());
+        (full_name, rest)
+    }
 }
 
 pub(crate) trait Displayable {
@@ -91,6 +242,10 @@ pub(crate) trait Displayable {
         self.image().resource_url()
     }
 
+    fn image_resource_url_absolute(&self, base: &str) -> Option<String> {
+        self.image().resource_url_absolute(base)
+    }
+
     fn image_alt(&self) -> &str {
         self.image().alt()
     }
@@ -98,15 +253,25 @@ pub(crate) trait Displayable {
 
 #[derive(FromRow, Clone, Debug)]
 pub(crate) struct RobotTextLink {
-    pub(crate) id: i32,
-    pub(crate) robot_number: i32,
+    // Text links are made from the number and ident, so the id is never needed
+    #[allow(dead_code)]
+    pub(crate) id: RobotId,
+    pub(crate) robot_number: RobotNumber,
     pub(crate) ident: String,
     pub(crate) prefix: String,
     pub(crate) suffix: String,
     pub(crate) plural: Option<String>,
+    // Text links only show the name, which the warning does not apply to
+    #[allow(dead_code)]
     pub(crate) content_warning: Option<String>,
 }
 
+impl RobotTextLink {
+    /// The columns of the robots table to select in order to build this struct.
+    pub(crate) const COLUMNS: &'static str =
+        "id, robot_number, ident, prefix, suffix, plural, content_warning";
+}
+
 impl Linkable for RobotTextLink {
     fn key(&self) -> RobotKey<'_> {
         RobotKey {
@@ -128,8 +293,8 @@ impl Named for RobotTextLink {
 
 #[derive(FromRow, Clone, Debug)]
 pub(crate) struct RobotPreview {
-    pub(crate) id: i32,
-    pub(crate) robot_number: i32,
+    pub(crate) id: RobotId,
+    pub(crate) robot_number: RobotNumber,
     pub(crate) ident: String,
     pub(crate) prefix: String,
     pub(crate) suffix: String,
@@ -138,6 +303,36 @@ pub(crate) struct RobotPreview {
     pub(crate) image_thumb_path: Option<String>,
     pub(crate) alt: Option<String>,
     pub(crate) custom_alt: Option<String>,
+    pub(crate) tweet_time: DateTime<Utc>,
+}
+
+impl RobotPreview {
+    /// The columns of the robots table to select in order to build this struct.
+    pub(crate) const COLUMNS: &'static str =
+        "id, robot_number, ident, prefix, suffix, plural, content_warning, image_thumb_path, \
+        alt, custom_alt, tweet_time";
+}
+
+/// Robot previews are ordered by number, with robots sharing a number ordered by id, matching the
+/// order used when listing all robots. Two previews are equal if they are of the same robot.
+impl PartialEq for RobotPreview {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RobotPreview {}
+
+impl PartialOrd for RobotPreview {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RobotPreview {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.robot_number, self.id).cmp(&(other.robot_number, other.id))
+    }
 }
 
 impl Linkable for RobotPreview {
@@ -171,8 +366,8 @@ impl Displayable for RobotPreview {
 
 #[derive(FromRow, Clone, Debug)]
 pub(crate) struct RobotFull {
-    pub(crate) id: i32,
-    pub(crate) robot_number: i32,
+    pub(crate) id: RobotId,
+    pub(crate) robot_number: RobotNumber,
     pub(crate) ident: String,
     pub(crate) prefix: String,
     pub(crate) suffix: String,
@@ -183,6 +378,14 @@ pub(crate) struct RobotFull {
     pub(crate) custom_alt: Option<String>,
     pub(crate) body: String,
     pub(crate) tweet_id: i64,
+    pub(crate) tweet_time: DateTime<Utc>,
+}
+
+impl RobotFull {
+    /// The columns of the robots table to select in order to build this struct.
+    pub(crate) const COLUMNS: &'static str =
+        "id, robot_number, ident, prefix, suffix, plural, content_warning, image_path, \
+        alt, custom_alt, body, tweet_id, tweet_time";
 }
 
 impl Linkable for RobotFull {
@@ -224,3 +427,254 @@ impl Count {
         (((self.count.max(0) - 1) / (page_size as i64)) + 1) as u32
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    thread_local! {
+        /// Overrides whether robot images exist for tests on this thread, so that they do not
+        /// depend on what is in the images directory.
+        // A const initialiser needs a newer Rust than the one used for Docker builds
+        #[allow(clippy::missing_const_for_thread_local)]
+        pub(super) static IMAGES_EXIST: Cell<Option<bool>> = Cell::new(None);
+    }
+
+    /// Treats every robot image as existing for the current thread, until the returned guard is
+    /// dropped.
+    pub(crate) fn assume_images_exist() -> AssumeImagesExist {
+        AssumeImagesExist(IMAGES_EXIST.with(|exist| exist.replace(Some(true))))
+    }
+
+    pub(crate) struct AssumeImagesExist(Option<bool>);
+
+    impl Drop for AssumeImagesExist {
+        fn drop(&mut self) {
+            IMAGES_EXIST.with(|exist| exist.set(self.0));
+        }
+    }
+
+    /// A preview of a robot with the given id and number, for tests which only care about those.
+    pub(crate) fn preview(id: i32, robot_number: i32) -> RobotPreview {
+        RobotPreview {
+            id: RobotId(id),
+            robot_number: RobotNumber(robot_number),
+            ident: "tea".to_owned(),
+            prefix: "Tea".to_owned(),
+            suffix: "bot".to_owned(),
+            plural: None,
+            content_warning: None,
+            image_thumb_path: None,
+            alt: None,
+            custom_alt: None,
+            tweet_time: Utc::now(),
+        }
+    }
+
+    pub(crate) fn full(id: i32, robot_number: i32) -> RobotFull {
+        RobotFull {
+            id: RobotId(id),
+            robot_number: RobotNumber(robot_number),
+            ident: "tea".to_owned(),
+            prefix: "Tea".to_owned(),
+            suffix: "bot".to_owned(),
+            plural: None,
+            content_warning: None,
+            image_path: None,
+            alt: None,
+            custom_alt: None,
+            body: "Teabot makes you a cup of tea when you need one".to_owned(),
+            tweet_id: 1,
+            tweet_time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn previews_are_ordered_by_number_then_id() {
+        let mut previews = [preview(5, 2), preview(3, 10), preview(4, 2), preview(1, -1)];
+        previews.sort();
+
+        let order = previews.iter().map(|robot| (robot.robot_number.0, robot.id.0)).collect::<Vec<_>>();
+        assert_eq!(order, vec![(-1, 1), (2, 4), (2, 5), (10, 3)]);
+    }
+
+    #[test]
+    fn previews_are_equal_if_they_are_the_same_robot() {
+        let mut renamed = preview(1, 2);
+        renamed.prefix = "Coffee".to_owned();
+
+        assert_eq!(preview(1, 2), renamed);
+        assert_ne!(preview(1, 2), preview(3, 2));
+    }
+
+    fn name<'a>(prefix: &'a str, suffix: &'a str, plural: Option<&'a str>) -> RobotName<'a> {
+        RobotName {
+            prefix,
+            suffix,
+            plural,
+        }
+    }
+
+    #[test]
+    fn image_existence_is_cached() {
+        let path = std::env::temp_dir().join(format!("sbb_archive_test_{}.png", std::process::id()));
+
+        std::fs::write(&path, b"").unwrap();
+        assert!(cached_is_file(path.clone()));
+
+        // The file is remembered as existing until the cached answer expires
+        std::fs::remove_file(&path).unwrap();
+        assert!(cached_is_file(path.clone()));
+        assert!(!path.is_file());
+    }
+
+    #[test]
+    fn image_url_is_under_robot_images() {
+        assert_eq!(image_url("tea.png"), "/robot_images/tea.png");
+    }
+
+    #[test]
+    fn absolute_image_url_with_and_without_trailing_slash() {
+        let _images = assume_images_exist();
+
+        let image = RobotImage {
+            file_name: Some("tea.png"),
+            orig_alt: None,
+            custom_alt: None,
+        };
+
+        assert_eq!(image.resource_url().as_deref(), Some("/robot_images/tea.png"));
+        assert_eq!(
+            image.resource_url_absolute("https://example.org").as_deref(),
+            Some("https://example.org/robot_images/tea.png")
+        );
+        assert_eq!(
+            image.resource_url_absolute("https://example.org/").as_deref(),
+            Some("https://example.org/robot_images/tea.png")
+        );
+    }
+
+    #[test]
+    fn missing_images_have_no_absolute_url() {
+        let image = RobotImage {
+            file_name: None,
+            orig_alt: None,
+            custom_alt: None,
+        };
+        assert_eq!(image.resource_url_absolute("https://example.org/"), None);
+    }
+
+    #[test]
+    fn links_include_base_path() {
+        let key = RobotKey {
+            robot_number: RobotNumber(42),
+            ident: "tea",
+        };
+        assert_eq!(key.page_link(), "/robot/42/tea");
+        assert_eq!(key.short_link(), "/r/42");
+
+        let _base_path = base_path::tests::set_for_thread("archive");
+        assert_eq!(key.page_link(), "/archive/robot/42/tea");
+        assert_eq!(key.short_link(), "/archive/r/42");
+    }
+
+    #[test]
+    fn image_urls_include_base_path() {
+        let _images = assume_images_exist();
+        let _base_path = base_path::tests::set_for_thread("archive");
+
+        let image = RobotImage {
+            file_name: Some("tea.png"),
+            orig_alt: None,
+            custom_alt: None,
+        };
+
+        assert_eq!(image.resource_url().as_deref(), Some("/archive/robot_images/tea.png"));
+        assert_eq!(
+            image.resource_url_absolute("https://example.org/").as_deref(),
+            Some("https://example.org/archive/robot_images/tea.png")
+        );
+    }
+
+    #[test]
+    fn missing_images_have_no_url() {
+        let missing = RobotImage {
+            file_name: Some("sbb_archive_test_missing.png"),
+            orig_alt: None,
+            custom_alt: None,
+        };
+        assert_eq!(missing.resource_url(), None);
+
+        let no_file = RobotImage {
+            file_name: None,
+            orig_alt: None,
+            custom_alt: None,
+        };
+        assert_eq!(no_file.resource_url(), None);
+    }
+
+    #[test]
+    fn slug_is_lowercase_ascii() {
+        assert_eq!(name("Tea", "bot", None).slug(), "teabot");
+        assert_eq!(name("Crème Brûlée", "bot", None).slug(), "creme-bruleebot");
+    }
+
+    #[test]
+    fn slug_collapses_punctuation_into_single_hyphens() {
+        assert_eq!(name("Salt- and Pepper", "bot", Some("s")).slug(), "salt-and-pepperbots");
+        assert_eq!(name("\"Salt- and Pepper", "bot", Some("s!")).slug(), "salt-and-pepperbots");
+        assert_eq!(name("...", "", None).slug(), "");
+    }
+
+    struct TestRobot {
+        prefix: &'static str,
+        suffix: &'static str,
+        plural: Option<&'static str>,
+    }
+
+    impl Named for TestRobot {
+        fn name(&self) -> RobotName<'_> {
+            name(self.prefix, self.suffix, self.plural)
+        }
+    }
+
+    #[test]
+    fn suffix_offset_simple() {
+        let teabot = name("Tea", "bot", None);
+        assert_eq!(teabot.suffix_offset_in_full_name(), 3);
+        assert_eq!(&teabot.full_name()[teabot.suffix_offset_in_full_name()..], "bot");
+
+        let saltbots = name("Salt", "bot", Some("s"));
+        assert_eq!(saltbots.suffix_offset_in_full_name(), 4);
+        assert_eq!(&saltbots.full_name()[saltbots.suffix_offset_in_full_name()..], "bots");
+    }
+
+    #[test]
+    fn suffix_offset_acronym() {
+        let robots = name("Very Tiny ", "R.O.B.O.T", Some(".S."));
+        assert_eq!(robots.suffix_offset_in_full_name(), 10);
+        assert_eq!(&robots.full_name()[robots.suffix_offset_in_full_name()..], "R.O.B.O.T.S.");
+    }
+
+    #[test]
+    fn suffix_offset_is_in_bytes() {
+        let creme = name("Crème Brûlée", "bot", None);
+        assert_eq!(&creme.full_name()[creme.suffix_offset_in_full_name()..], "bot");
+    }
+
+    #[test]
+    fn full_name_parts_join_to_full_name() {
+        let robots = [
+            TestRobot { prefix: "Tea", suffix: "bot", plural: None },
+            TestRobot { prefix: "Salt", suffix: "bot", plural: Some("s") },
+            TestRobot { prefix: "Very Tiny ", suffix: "R.O.B.O.T", plural: Some(".S.") },
+            TestRobot { prefix: "Crème Brûlée", suffix: "bot", plural: None },
+        ];
+
+        for robot in &robots {
+            let (prefix, rest) = robot.full_name_parts();
+            assert_eq!(prefix, robot.prefix);
+            assert_eq!(format!("{}{}", prefix, rest), robot.full_name());
+        }
+    }
+}
@@ -1,17 +1,131 @@
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use log::warn;
+use serde::Deserialize;
 use sqlx::postgres::PgPool;
 use unidecode::unidecode;
 
 use crate::error::{SiteReportResult, IntoReport};
-use crate::robots::RobotPreview;
+use crate::robots::{RobotId, RobotNumber, RobotPreview};
 
 const MAX_ROBOTS: i32 = 48;
 
-pub(crate) async fn search(db_pool: &PgPool, query: &str) -> SiteReportResult<Vec<RobotPreview>> {
-    let query_terms = match to_query_terms(query) {
+/// Whether a warning about pg_trgm being missing has been logged yet.
+static TRGM_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// A Postgres text search configuration to use for full text search. The `ts` column of the
+/// robots table should be generated using the same configuration, otherwise stemming of the
+/// query will not match stemming of the robot descriptions.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SearchLanguage(&'static str);
+
+impl SearchLanguage {
+    /// The text search configurations which ship with Postgres.
+    const KNOWN: &'static [&'static str] = &[
+        "simple", "arabic", "danish", "dutch", "english", "finnish", "french", "german", "greek",
+        "hungarian", "indonesian", "irish", "italian", "lithuanian", "nepali", "norwegian",
+        "portuguese", "romanian", "russian", "spanish", "swedish", "tamil", "turkish",
+    ];
+
+    pub(crate) const ENGLISH: SearchLanguage = SearchLanguage("english");
+
+    /// Returns the text search configuration with the given name, or `None` if it is not one of
+    /// the configurations built into Postgres.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::KNOWN
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(name))
+            .map(|known| SearchLanguage(known))
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        self.0
+    }
+}
+
+impl Default for SearchLanguage {
+    fn default() -> Self {
+        Self::ENGLISH
+    }
+}
+
+/// Words which are left out of the terms used to match robot idents, because they appear in so
+/// many queries and names that matching on them adds noise. Full text search is unaffected, since
+/// the text search configuration has stop words of its own.
+#[derive(Clone, Debug)]
+pub(crate) struct StopWords(HashSet<String>);
+
+impl StopWords {
+    const ENGLISH: &'static [&'static str] = &[
+        "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of",
+        "on", "or", "that", "the", "this", "to", "with",
+    ];
+
+    /// Parses a comma-separated list of stop words. Blank entries are ignored, so "," gives an
+    /// empty list.
+    pub(crate) fn from_list(list: &str) -> Self {
+        StopWords(list
+            .split(',')
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect())
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.0.contains(word)
+    }
+}
+
+impl Default for StopWords {
+    fn default() -> Self {
+        StopWords(Self::ENGLISH
+            .iter()
+            .map(|&word| word.to_owned())
+            .collect())
+    }
+}
+
+/// The order in which search results are listed.
+#[derive(Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SearchOrder {
+    /// Number matches first, then ident matches from most to least similar, then full text
+    /// matches from most to least relevant.
+    Relevance,
+    /// The same order as the list of all robots.
+    Number,
+}
+
+// Deriving this requires `#[default]`, which the Rust version used for Docker builds lacks
+#[allow(clippy::derivable_impls)]
+impl Default for SearchOrder {
+    fn default() -> Self {
+        Self::Relevance
+    }
+}
+
+/// The result of a search, distinguishing a query with nothing to search for from a query which
+/// genuinely matched no robots.
+#[derive(Clone, Debug)]
+pub(crate) enum SearchOutcome {
+    /// The query did not contain any searchable terms.
+    NoQuery,
+    /// The robots found by the search, which may be empty.
+    Results(Vec<RobotPreview>),
+}
+
+pub(crate) async fn search(
+    db_pool: &PgPool,
+    query: &str,
+    language: SearchLanguage,
+    stop_words: &StopWords,
+    order: SearchOrder,
+) -> SiteReportResult<SearchOutcome>
+{
+    let query_terms = match to_query_terms(query, stop_words) {
         Some(query_terms) => query_terms,
-        None => return Ok(Vec::new()),
+        None => return Ok(SearchOutcome::NoQuery),
     };
 
     // Vector for storing the robots found by the search
@@ -20,8 +134,11 @@ pub(crate) async fn search(db_pool: &PgPool, query: &str) -> SiteReportResult<Ve
     // We only want to show each robot once, so keep track of the ids
     let mut found_ids = HashSet::new();
 
-    let query_numbers = to_query_numbers(&query_terms);
+    let query_numbers = to_query_numbers(query);
 
+    // Exact number matches are searched for first so that they are always at the top of the
+    // results, and are never pushed out by ident or full text matches when the results are
+    // truncated to `MAX_ROBOTS`
     if !query_numbers.is_empty() {
         let number_matches = search_by_number(db_pool, &query_numbers, MAX_ROBOTS)
             .await
@@ -33,9 +150,19 @@ pub(crate) async fn search(db_pool: &PgPool, query: &str) -> SiteReportResult<Ve
             }
     }
 
-    let ident_matches = search_by_ident(db_pool, &query_terms, MAX_ROBOTS - found_robots.len() as i32)
-        .await
-        .map_err(|err| err.into_report(format!("failed search by idents {:?}", query_terms)))?;
+    let ident_limit = MAX_ROBOTS - found_robots.len() as i32;
+
+    let ident_matches = match search_by_ident(db_pool, &query_terms, ident_limit).await {
+        // Without pg_trgm, fall back to plain substring matching so that search still works
+        Err(err) if is_missing_trgm(&err) => {
+            if !TRGM_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("pg_trgm extension is not installed, falling back to unranked ident search: {}", err);
+            }
+            search_by_ident_substring(db_pool, &query_terms, ident_limit).await
+        },
+        res => res,
+    }
+    .map_err(|err| err.into_report(format!("failed search by idents {:?}", query_terms)))?;
 
     for robot in ident_matches {
         if !found_ids.contains(&robot.id) {
@@ -44,7 +171,7 @@ pub(crate) async fn search(db_pool: &PgPool, query: &str) -> SiteReportResult<Ve
         }
     }
 
-    let full_text_matches = search_by_full_text(db_pool, query, MAX_ROBOTS - found_robots.len() as i32)
+    let full_text_matches = search_by_full_text(db_pool, query, language, MAX_ROBOTS - found_robots.len() as i32)
         .await
         .map_err(|err| err.into_report(format!("failed to search by full text {:?}", query)))?;
 
@@ -55,19 +182,45 @@ pub(crate) async fn search(db_pool: &PgPool, query: &str) -> SiteReportResult<Ve
         }
     }
 
-    Ok(found_robots)
+    sort_results(&mut found_robots, order);
+
+    Ok(SearchOutcome::Results(found_robots))
 }
 
-fn to_query_terms(query: &str) -> Option<Vec<String>> {
+/// Puts the robots found by a search into the order asked for. The robots are found in order of
+/// relevance, so they only need sorting if a different order was asked for.
+fn sort_results(robots: &mut [RobotPreview], order: SearchOrder) {
+    match order {
+        SearchOrder::Relevance => (),
+        SearchOrder::Number => robots.sort_unstable(),
+    }
+}
+
+/// Applies the same transformation to the word as the transformation that Smolbotbot applies to
+/// robot name prefixes to generate the ident: convert to lowercase ASCII then remove all
+/// non-alphanumeric characters. Matching query terms against idents relies on this giving only
+/// lowercase ASCII alphanumerics, and on it leaving its own output unchanged.
+fn to_ident_form(word: &str) -> String {
+    let mut ident = unidecode(word).to_lowercase();
+    ident.retain(|char| char.is_ascii_alphanumeric());
+    ident
+}
+
+/// Derives the terms to match robot idents against from the query. Returns `None` if the query
+/// has no searchable words at all; if its words are all stop words, the terms are empty but the
+/// query is still searched by number and full text.
+fn to_query_terms(query: &str, stop_words: &StopWords) -> Option<Vec<String>> {
     // Split the query by whitespace and convert to lowercase ASCII
     let words = query
         .split_whitespace()
+        // Treat standalone "&" and "+" as "and", so that "salt & pepper" gives the same terms as
+        // "salt and pepper" rather than having the symbols stripped away below
+        .map(|word| match word {
+            "&" | "+" => "and",
+            word => word,
+        })
         .filter_map(|word| {
-            // Apply the same transformation to the word as the transformation that Smolbotbot
-            // applies to robot name prefixes to generate the ident: convert to lowercase ASCII
-            // then remove all non-alphanumeric characters
-            let mut word_lower_ascii = unidecode(word).to_lowercase();
-            word_lower_ascii.retain(|char| char.is_ascii_alphanumeric());
+            let word_lower_ascii = to_ident_form(word);
 
             // Discard words which do not have any alphanumeric characters
             if word_lower_ascii.is_empty() {
@@ -84,7 +237,7 @@ fn to_query_terms(query: &str) -> Option<Vec<String>> {
 
     let mut query_terms = Vec::new();
 
-    for word in words {
+    for word in words.into_iter().filter(|word| !stop_words.contains(word)) {
         // Create a copy of any words ending with "bot", with the "bot" removed
         if let Some(trimmed_word) = word.strip_suffix("bot").or(word.strip_suffix("bots")) {
             if !trimmed_word.is_empty() {
@@ -98,28 +251,45 @@ fn to_query_terms(query: &str) -> Option<Vec<String>> {
     Some(query_terms)
 }
 
-fn to_query_numbers(query_terms: &[String]) -> Vec<i32> {
-    query_terms
-        .iter()
-        .filter_map(|term| term.parse::<i32>().ok())
+fn to_query_numbers(query: &str) -> Vec<RobotNumber> {
+    // Numbers are taken from the raw query rather than the query terms, since the terms have had
+    // their non-alphanumeric characters removed, which would turn "-1" into "1"
+    query
+        .split_whitespace()
+        .filter_map(to_query_number)
         .collect::<Vec<_>>()
 }
 
+/// Parses a single word of a query as a robot number, allowing it to be written like "#42" and to
+/// be surrounded by punctuation like "(42)". Words with anything else in them, like "C3PO", are
+/// names rather than numbers.
+fn to_query_number(word: &str) -> Option<RobotNumber> {
+    let word = word.trim_matches(|c: char| c.is_ascii_punctuation() && c != '-' && c != '#');
+    let word = word.strip_prefix('#').unwrap_or(word);
+    let digits = word.strip_prefix('-').unwrap_or(word);
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    word.parse::<i32>().ok().map(RobotNumber)
+}
+
 async fn search_by_number(
     db_pool: &PgPool,
-    query_numbers: &[i32],
+    query_numbers: &[RobotNumber],
     limit: i32,
 ) -> sqlx::Result<Vec<RobotPreview>>
 {
-    sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_thumb_path, \
-            alt, custom_alt \
+    sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
         WHERE robot_number = ANY($1) \
-        LIMIT $2"
-    )
-    .bind(&query_numbers)
+        ORDER BY array_position($1, robot_number), id \
+        LIMIT $2",
+        RobotPreview::COLUMNS
+    ))
+    .bind(query_numbers.iter().map(|number| number.0).collect::<Vec<_>>())
     .bind(limit)
     .fetch_all(db_pool)
     .await
@@ -131,10 +301,8 @@ async fn search_by_ident(
     limit: i32,
 ) -> sqlx::Result<Vec<RobotPreview>>
 {
-    sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_thumb_path, \
-            alt, custom_alt \
+    sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots \
         CROSS JOIN LATERAL unnest($1) AS query_terms(query_term) \
         WHERE \
@@ -142,31 +310,291 @@ async fn search_by_ident(
             AND ident ILIKE '%' || query_term || '%' \
         GROUP BY id \
         ORDER BY min(ident <-> query_term) \
-        LIMIT $2"
-    )
-    .bind(&query_terms)
+        LIMIT $2",
+        RobotPreview::COLUMNS
+    ))
+    .bind(query_terms)
     .bind(limit)
     .fetch_all(db_pool)
     .await
 }
 
+/// Ident search which does not depend on pg_trgm, for databases without the extension. Robots are
+/// matched if their ident contains any of the query terms, but are not ranked by similarity.
+async fn search_by_ident_substring(
+    db_pool: &PgPool,
+    query_terms: &[String],
+    limit: i32,
+) -> sqlx::Result<Vec<RobotPreview>>
+{
+    sqlx::query_as(&format!(
+        "SELECT {} \
+        FROM robots \
+        WHERE ident ILIKE ANY(SELECT '%' || query_term || '%' FROM unnest($1) AS query_terms(query_term)) \
+        ORDER BY robot_number, id \
+        LIMIT $2",
+        RobotPreview::COLUMNS
+    ))
+    .bind(query_terms)
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await
+}
+
+/// Finds robots whose idents are similar to the given one, most similar first, excluding the robot
+/// with the given id. Returns no robots if pg_trgm is not installed.
+pub(crate) async fn related(
+    db_pool: &PgPool,
+    id: RobotId,
+    ident: &str,
+    limit: i32,
+) -> sqlx::Result<Vec<RobotPreview>>
+{
+    let res = sqlx::query_as(&format!(
+        "SELECT {} \
+        FROM robots \
+        WHERE id <> $1 \
+        ORDER BY ident <-> $2, id \
+        LIMIT $3",
+        RobotPreview::COLUMNS
+    ))
+    .bind(id)
+    .bind(ident)
+    .bind(limit)
+    .fetch_all(db_pool)
+    .await;
+
+    match res {
+        Err(err) if is_missing_trgm(&err) => {
+            if !TRGM_WARNED.swap(true, Ordering::Relaxed) {
+                warn!("pg_trgm extension is not installed, related robots will not be shown: {}", err);
+            }
+            Ok(Vec::new())
+        },
+        res => res,
+    }
+}
+
+/// Returns true if the error was caused by the pg_trgm operators used by [search_by_ident] not
+/// existing.
+fn is_missing_trgm(err: &sqlx::Error) -> bool {
+    // SQLSTATE 42883 is undefined_function, which is also used for undefined operators
+    const UNDEFINED_FUNCTION: &str = "42883";
+
+    err.as_database_error()
+        .and_then(|err| err.code())
+        .as_deref()
+        == Some(UNDEFINED_FUNCTION)
+}
+
 async fn search_by_full_text(
     db_pool: &PgPool,
     query: &str,
+    language: SearchLanguage,
     limit: i32
 ) -> sqlx::Result<Vec<RobotPreview>>
 {
-    sqlx::query_as(
-        "SELECT \
-            id, robot_number, ident, prefix, suffix, plural, content_warning, image_thumb_path, \
-            alt, custom_alt \
+    sqlx::query_as(&format!(
+        "SELECT {} \
         FROM robots
-        WHERE ts @@ replace(plainto_tsquery('english', $1)::text, '&', '|')::tsquery
-        ORDER BY ts_rank(ts, replace(plainto_tsquery('english', $1)::text, '&', '|')::tsquery) DESC
-        LIMIT $2"
-    )
+        WHERE ts @@ replace(plainto_tsquery($3::regconfig, $1)::text, '&', '|')::tsquery
+        ORDER BY ts_rank(ts, replace(plainto_tsquery($3::regconfig, $1)::text, '&', '|')::tsquery) DESC
+        LIMIT $2",
+        RobotPreview::COLUMNS
+    ))
     .bind(query)
     .bind(limit)
+    .bind(language.name())
     .fetch_all(db_pool)
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::db::tests::{connect_empty, drop_schema, insert_robot};
+    use crate::robots::tests::preview;
+
+    use super::*;
+
+    fn ids(robots: &[RobotPreview]) -> Vec<i32> {
+        robots.iter().map(|robot| robot.id.0).collect()
+    }
+
+    #[test]
+    fn results_keep_relevance_order() {
+        let mut robots = [preview(1, 30), preview(2, 10), preview(3, 20)];
+        sort_results(&mut robots, SearchOrder::Relevance);
+        assert_eq!(ids(&robots), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn results_sorted_by_number() {
+        let mut robots = [preview(1, 30), preview(4, 10), preview(2, 10), preview(3, 20)];
+        sort_results(&mut robots, SearchOrder::Number);
+        assert_eq!(ids(&robots), vec![2, 4, 3, 1]);
+    }
+
+    #[test]
+    fn search_language_accepts_known_names_in_any_case() {
+        assert_eq!(SearchLanguage::from_name("english").map(SearchLanguage::name), Some("english"));
+        assert_eq!(SearchLanguage::from_name("French").map(SearchLanguage::name), Some("french"));
+        assert_eq!(SearchLanguage::from_name("SIMPLE").map(SearchLanguage::name), Some("simple"));
+    }
+
+    #[test]
+    fn search_language_rejects_unknown_names() {
+        assert!(SearchLanguage::from_name("klingon").is_none());
+        assert!(SearchLanguage::from_name("").is_none());
+        assert!(SearchLanguage::from_name("english; DROP TABLE robots").is_none());
+        assert!(SearchLanguage::from_name(" english").is_none());
+    }
+
+    /// A spread of scripts and symbols, including characters which unidecode turns into uppercase
+    /// letters, punctuation or several characters.
+    const UNICODE_SAMPLES: &[&str] = &[
+        "Teabot", "Crème Brûlée", "Straße", "ÆØÅ", "ǅemal", "Ⅻ", "①②", "ﬁsh", "Ωmega", "Привет",
+        "東京", "ｆｕｌｌｗｉｄｔｈ", "😀🤖", "a\u{0301}", "İstanbul", "ﾊﾝｶｸ", "\u{200D}", "¼", "™", "",
+    ];
+
+    fn assert_ident_form(input: &str) {
+        let ident = to_ident_form(input);
+        assert!(
+            ident.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()),
+            "{:?} gave {:?}, which is not lowercase ASCII alphanumeric", input, ident
+        );
+        assert_eq!(to_ident_form(&ident), ident, "{:?} is not idempotent", input);
+    }
+
+    #[test]
+    fn ident_form_is_stable_for_samples() {
+        for sample in UNICODE_SAMPLES {
+            assert_ident_form(sample);
+        }
+    }
+
+    #[test]
+    fn ident_form_is_stable_for_every_bmp_char() {
+        for c in (0..=0xffff).filter_map(std::char::from_u32) {
+            assert_ident_form(&c.to_string());
+        }
+    }
+
+    #[test]
+    fn ident_form_matches_smolbotbot() {
+        assert_eq!(to_ident_form("Crème-Brûlée"), "cremebrulee");
+        assert_eq!(to_ident_form("Salt'n'Pepper"), "saltnpepper");
+        assert_eq!(to_ident_form("R2"), "r2");
+    }
+
+    fn terms(query: &str) -> Vec<String> {
+        to_query_terms(query, &StopWords::default()).unwrap()
+    }
+
+    #[test]
+    fn query_terms_treat_symbols_as_and() {
+        assert_eq!(terms("salt & pepper"), terms("salt and pepper"));
+        assert_eq!(terms("salt + pepper"), terms("salt and pepper"));
+
+        // The symbols are replaced rather than just dropped, so they match "and" even when it is
+        // not a stop word
+        let no_stop_words = StopWords::from_list("");
+        assert_eq!(
+            to_query_terms("salt & pepper", &no_stop_words),
+            Some(vec!["salt".to_owned(), "and".to_owned(), "pepper".to_owned()])
+        );
+        assert_eq!(
+            to_query_terms("salt + pepper", &no_stop_words),
+            to_query_terms("salt and pepper", &no_stop_words)
+        );
+    }
+
+    #[test]
+    fn query_terms_drop_stop_words() {
+        assert_eq!(terms("the teabot"), vec!["tea", "teabot"]);
+        assert_eq!(to_query_terms("the a an", &StopWords::default()), Some(Vec::new()));
+        assert_eq!(to_query_terms("& ...", &StopWords::default()), Some(Vec::new()));
+        assert_eq!(to_query_terms("...", &StopWords::default()), None);
+    }
+
+    #[test]
+    fn stop_words_from_list() {
+        let stop_words = StopWords::from_list(" The, robot ,,");
+        assert!(stop_words.contains("the"));
+        assert!(stop_words.contains("robot"));
+        assert!(!stop_words.contains(""));
+        assert!(!stop_words.contains("and"));
+
+        assert_eq!(to_query_terms("the teabot robot", &stop_words), Some(vec!["tea".to_owned(), "teabot".to_owned()]));
+        assert_eq!(StopWords::from_list(",").0.len(), 0);
+    }
+
+    fn numbers(query: &str) -> Vec<i32> {
+        to_query_numbers(query).into_iter().map(|number| number.0).collect()
+    }
+
+    #[test]
+    fn query_numbers_keep_negative_sign() {
+        assert_eq!(numbers("-1"), vec![-1]);
+    }
+
+    #[test]
+    fn query_numbers_strip_hash_and_punctuation() {
+        assert_eq!(numbers("#42"), vec![42]);
+        assert_eq!(numbers("42)"), vec![42]);
+        assert_eq!(numbers("(#7), 8."), vec![7, 8]);
+    }
+
+    #[test]
+    fn query_numbers_ignore_names_containing_digits() {
+        assert!(numbers("C3PO").is_empty());
+        assert!(numbers("teabot2").is_empty());
+        assert!(numbers("mk2bot").is_empty());
+        assert!(numbers("1-2 --1 # -").is_empty());
+    }
+
+    /// Searches an archive of a few robots, all of whose names end in "bot".
+    async fn search_archive(schema: &'static str, query: &str) -> SearchOutcome {
+        let pool = connect_empty(schema).await;
+
+        insert_robot(&pool, 3, "bot", "Bot", "bot", "A robot made of smaller robots").await;
+        insert_robot(&pool, 7, "tea", "Tea", "bot", "Teabot makes you a cup of tea").await;
+        insert_robot(&pool, 42, "kettle", "Kettle", "bot", "Kettlebot boils water for tea").await;
+        insert_robot(&pool, 50, "botany", "Botany", "bot", "Botanybot looks after the plants").await;
+
+        let outcome = search(&pool, query, SearchLanguage::default(), &StopWords::default(), SearchOrder::Relevance)
+            .await
+            .unwrap();
+
+        drop_schema(schema).await;
+
+        outcome
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn number_match_comes_first() {
+        let robots = match search_archive("test_search_number", "42 bot").await {
+            SearchOutcome::Results(robots) => robots,
+            SearchOutcome::NoQuery => panic!("query was not searched"),
+        };
+
+        assert!(robots.len() > 1);
+        assert_eq!(robots[0].robot_number.0, 42);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn empty_query_is_not_searched() {
+        assert!(matches!(search_archive("test_search_empty", "").await, SearchOutcome::NoQuery));
+        assert!(matches!(search_archive("test_search_blank", "  ...  ").await, SearchOutcome::NoQuery));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "requires a database"]
+    async fn unmatched_query_has_no_results() {
+        match search_archive("test_search_unmatched", "xylophone").await {
+            SearchOutcome::Results(robots) => assert!(robots.is_empty()),
+            SearchOutcome::NoQuery => panic!("query was not searched"),
+        }
+    }
+}
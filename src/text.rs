@@ -0,0 +1,136 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, Utc};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Truncates the string to at most `max` grapheme clusters, replacing the end of the string with
+/// an ellipsis if it was truncated. Truncating on grapheme boundaries rather than bytes or chars
+/// ensures that multi-codepoint characters such as emoji ZWJ sequences are never split.
+pub(crate) fn truncate_graphemes(s: &str, max: usize) -> Cow<'_, str> {
+    const ELLIPSIS: &str = "\u{2026}";
+
+    // Find the byte index of the last grapheme which can be kept, leaving space for the ellipsis
+    let mut indices = s.grapheme_indices(true).map(|(i, _)| i);
+
+    let cut = match indices.nth(max.saturating_sub(1)) {
+        Some(cut) => cut,
+        None => return Cow::Borrowed(s),
+    };
+
+    // If there are no more graphemes after this one, the string already fits
+    if indices.next().is_none() {
+        return Cow::Borrowed(s);
+    }
+
+    let mut truncated = s[..cut].trim_end().to_owned();
+    truncated.push_str(ELLIPSIS);
+    Cow::Owned(truncated)
+}
+
+/// Returns a human-readable description of how long before `now` the time `then` was, such as
+/// "3 days ago". Times less than a minute ago, or in the future, are described as "just now".
+pub(crate) fn relative_time(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = now.signed_duration_since(then);
+
+    let minutes = elapsed.num_minutes();
+    let hours = elapsed.num_hours();
+    let days = elapsed.num_days();
+
+    if minutes < 1 {
+        "just now".to_owned()
+    } else if hours < 1 {
+        units_ago(minutes, "minute")
+    } else if days < 1 {
+        units_ago(hours, "hour")
+    } else if days < 30 {
+        units_ago(days, "day")
+    } else if days < 360 {
+        units_ago(days / 30, "month")
+    } else {
+        // Switch to years before 12 months would be shown, so that the last few days of the
+        // year count as 1 year rather than 12 months
+        units_ago((days / 365).max(1), "year")
+    }
+}
+
+fn units_ago(n: i64, unit: &str) -> String {
+    if n == 1 {
+        format!("1 {} ago", unit)
+    } else {
+        format!("{} {}s ago", n, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &str = "\u{1F469}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+
+    #[test]
+    fn truncate_keeps_short_strings() {
+        assert_eq!(truncate_graphemes("small robot", 11), "small robot");
+        assert_eq!(truncate_graphemes("", 3), "");
+        assert!(matches!(truncate_graphemes("small robot", 20), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn truncate_replaces_end_with_ellipsis() {
+        assert_eq!(truncate_graphemes("small robot", 6), "small\u{2026}");
+        assert_eq!(truncate_graphemes("small robot", 7), "small\u{2026}");
+    }
+
+    #[test]
+    fn truncate_keeps_multi_codepoint_emoji_whole() {
+        let body = format!("teabot {}", FAMILY);
+
+        // The emoji is a single grapheme, so the string already fits
+        assert_eq!(truncate_graphemes(&body, 8), body);
+
+        let truncated = truncate_graphemes(&body, 7);
+        assert_eq!(truncated, "teabot\u{2026}");
+        assert!(!truncated.contains('\u{200D}'));
+
+        let long_body = format!("{}{} robot", FAMILY, FAMILY);
+        assert_eq!(truncate_graphemes(&long_body, 2), format!("{}\u{2026}", FAMILY));
+    }
+
+    fn ago(duration: chrono::Duration) -> String {
+        let now = Utc::now();
+        relative_time(now - duration, now)
+    }
+
+    #[test]
+    fn relative_time_recent() {
+        assert_eq!(ago(chrono::Duration::seconds(0)), "just now");
+        assert_eq!(ago(chrono::Duration::seconds(59)), "just now");
+        assert_eq!(ago(chrono::Duration::seconds(-3600)), "just now");
+        assert_eq!(ago(chrono::Duration::minutes(1)), "1 minute ago");
+        assert_eq!(ago(chrono::Duration::minutes(59)), "59 minutes ago");
+        assert_eq!(ago(chrono::Duration::hours(1)), "1 hour ago");
+        assert_eq!(ago(chrono::Duration::hours(23)), "23 hours ago");
+    }
+
+    #[test]
+    fn relative_time_days_and_months() {
+        assert_eq!(ago(chrono::Duration::days(1)), "1 day ago");
+        assert_eq!(ago(chrono::Duration::days(29)), "29 days ago");
+        assert_eq!(ago(chrono::Duration::days(30)), "1 month ago");
+        assert_eq!(ago(chrono::Duration::days(75)), "2 months ago");
+        assert_eq!(ago(chrono::Duration::days(359)), "11 months ago");
+    }
+
+    #[test]
+    fn relative_time_never_shows_twelve_months() {
+        for days in 360..365 {
+            assert_eq!(ago(chrono::Duration::days(days)), "1 year ago");
+        }
+    }
+
+    #[test]
+    fn relative_time_years() {
+        assert_eq!(ago(chrono::Duration::days(365)), "1 year ago");
+        assert_eq!(ago(chrono::Duration::days(729)), "1 year ago");
+        assert_eq!(ago(chrono::Duration::days(730)), "2 years ago");
+    }
+}
@@ -0,0 +1,37 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+
+use rustls::{NoClientAuth, ServerConfig};
+use rustls::internal::pemfile;
+
+/// Builds a rustls server config from a PEM-encoded certificate chain and private key. The key may
+/// be either PKCS #8 or RSA.
+pub fn load_config(cert_path: &str, key_path: &str) -> io::Result<ServerConfig> {
+    let certs = pemfile::certs(&mut BufReader::new(File::open(cert_path)?))
+        .map_err(|_| invalid_data(format!("invalid TLS certificate file {}", cert_path)))?;
+
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(key_path)?))
+        .map_err(|_| invalid_data(format!("invalid TLS key file {}", key_path)))?;
+
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(File::open(key_path)?))
+            .map_err(|_| invalid_data(format!("invalid TLS key file {}", key_path)))?;
+    }
+
+    let key = keys
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid_data(format!("no private key found in {}", key_path)))?;
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+
+    config
+        .set_single_cert(certs, key)
+        .map_err(|err| invalid_data(format!("invalid TLS certificate or key: {}", err)))?;
+
+    Ok(config)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}